@@ -1,4 +1,5 @@
 mod macros;
+mod table;
 
 use historian_hysteria::CompareLocations;
 use mull_it_over::Calculations;
@@ -25,12 +26,33 @@ fn day2(input: &str) {
         "Safe reports (dampened): {}",
         reports.get_amount_of_safe_reports(true)
     );
+
+    let summary = reports.summary();
+    table::print_table(
+        ("Summary", "Reports"),
+        [
+            ("Total", summary.total),
+            ("Safe", summary.safe),
+            ("Rescued by dampening", summary.rescued_by_dampening),
+        ],
+    );
+    table::print_table(("Failure reason", "Reports"), summary.failures);
+    table::print_table(("Levels", "Reports"), summary.lengths);
+    table::print_table(("Step size", "Steps"), summary.steps);
 }
 
 fn day3(input: &str) {
     let calculations = Calculations::from_str(input).expect("Could not parse input");
-    println!("Sum: {}", calculations.sum());
-    println!("Sum (with conditions): {}", calculations.sum_conditional());
+    println!(
+        "Sum: {}",
+        calculations.sum().expect("Calculations overflowed")
+    );
+    println!(
+        "Sum (with conditions): {}",
+        calculations
+            .sum_conditional()
+            .expect("Calculations overflowed")
+    );
 }
 
 fn day4(input: &str) {
@@ -41,17 +63,25 @@ fn day4(input: &str) {
 
 fn day5(input: &str) {
     let print_queue = PrintQueue::from_str(input).expect("Could not parse input");
-    println!("Correct updates sum: {}", print_queue.correct_updates_sum());
+    println!(
+        "Correct updates sum: {}",
+        print_queue
+            .correct_updates_sum()
+            .expect("Could not find middle pages")
+    );
     println!(
         "Incorrect updates sum: {}",
-        print_queue.incorrect_updates_sum()
+        print_queue
+            .incorrect_updates_sum()
+            .expect("Could not find middle pages")
     );
 }
 
 fn day6(input: &str) {
     let lab = Lab::from_str(input).expect("Could not parse input");
     println!("Tiles visited: {}", lab.find_guard_route_visits());
-    println!("Looping routes: {}", lab.find_route_loops());
+    let threads = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+    println!("Looping routes: {}", lab.find_route_loops_parallel(threads));
 }
 
 fn main() {
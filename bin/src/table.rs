@@ -0,0 +1,33 @@
+use std::fmt::Display;
+
+/// Prints a two-column table, with the first column left-aligned and the second right-aligned
+pub fn print_table<K: Display, V: Display>(
+    headers: (&str, &str),
+    rows: impl IntoIterator<Item = (K, V)>,
+) {
+    let rows: Vec<(String, String)> = rows
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+
+    let key_width = rows
+        .iter()
+        .map(|(key, _)| key.len())
+        .chain([headers.0.len()])
+        .max()
+        .unwrap_or_default();
+    let value_width = rows
+        .iter()
+        .map(|(_, value)| value.len())
+        .chain([headers.1.len()])
+        .max()
+        .unwrap_or_default();
+
+    println!(
+        "| {:<key_width$} | {:>value_width$} |",
+        headers.0, headers.1
+    );
+    println!("|-{:-<key_width$}-|-{:->value_width$}-|", "", "");
+    rows.iter()
+        .for_each(|(key, value)| println!("| {key:<key_width$} | {value:>value_width$} |"));
+}
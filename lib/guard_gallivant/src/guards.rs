@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{Coordinate, Direction, Event, GuardWalk, Lab, State};
+
+/// Two guards standing on the same cell at the same time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collision {
+    /// The amount of steps taken by every guard
+    pub time: usize,
+    pub position: Coordinate,
+    /// The indices of the two guards, in reading order
+    pub guards: (usize, usize),
+}
+
+/// Where a guard is at every point in time
+struct Trajectory {
+    positions: Vec<Coordinate>,
+    /// The time the guard starts walking in a loop, which lasts until the end of `positions`.
+    /// `None` if the guard leaves the lab right after `positions`.
+    cycle_start: Option<usize>,
+}
+
+impl Trajectory {
+    fn new(walk: GuardWalk) -> Self {
+        let (states, cycle_start) = walk.trace();
+
+        Self {
+            positions: states.into_iter().map(|(position, _)| position).collect(),
+            cycle_start,
+        }
+    }
+
+    /// The time from which the guard has either left the lab or is walking its loop
+    fn settled_at(&self) -> usize {
+        self.cycle_start.unwrap_or(self.positions.len())
+    }
+
+    fn at(&self, time: usize) -> Option<Coordinate> {
+        match self.cycle_start {
+            Some(start) if time >= start => {
+                let period = self.positions.len() - start;
+                Some(self.positions[start + (time - start) % period])
+            }
+            _ => self.positions.get(time).copied(),
+        }
+    }
+
+    /// The positions of the loop, indexed by their offset from the start of the loop
+    fn cycle(&self) -> Option<&[Coordinate]> {
+        Some(&self.positions[self.cycle_start?..])
+    }
+}
+
+/// Iterator over every guard in the lab walking in lockstep, see [`Lab::patrol`].
+///
+/// Yields the state of every guard after each step, where guards that already left the lab are `None`.
+/// Guards don't block each other, and the patrol only ends once every guard has left the lab.
+pub struct Patrol<'a> {
+    walks: Vec<Option<GuardWalk<'a>>>,
+}
+
+impl Iterator for Patrol<'_> {
+    type Item = Vec<Option<State>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let states: Vec<Option<State>> = self
+            .walks
+            .iter_mut()
+            .map(|slot| {
+                let walk = slot.as_mut()?;
+
+                let state = match walk.next() {
+                    Some(state) if state.event == Event::Looped => {
+                        walk.restart();
+                        walk.next()
+                    }
+                    state => state,
+                };
+
+                if state.is_none_or(|state| state.event == Event::Exited) {
+                    *slot = None;
+                }
+
+                state
+            })
+            .collect();
+
+        states.iter().any(Option::is_some).then_some(states)
+    }
+}
+
+impl Lab {
+    /// The starting position and direction of every guard, in reading order
+    pub fn guards(&self) -> &[(Coordinate, Direction)] {
+        &self.guards
+    }
+
+    /// Simulates the patrol of a single guard one step at a time
+    pub fn walk_guard(&self, guard: usize) -> Option<GuardWalk<'_>> {
+        let &(position, direction) = self.guards.get(guard)?;
+        Some(GuardWalk::from_state(self, position, direction, 0, 0))
+    }
+
+    /// Simulates every guard one step at a time, in lockstep
+    pub fn patrol(&self) -> Patrol<'_> {
+        Patrol {
+            walks: (0..self.guards.len())
+                .map(|guard| self.walk_guard(guard))
+                .collect(),
+        }
+    }
+
+    /// The cells visited by at least one guard
+    pub fn visited_by_any(&self) -> HashSet<Coordinate> {
+        self.guard_routes().flatten().collect()
+    }
+
+    /// The cells visited by every guard
+    pub fn visited_by_all(&self) -> HashSet<Coordinate> {
+        self.guard_routes()
+            .map(|route| route.collect::<HashSet<_>>())
+            .reduce(|all, route| &all & &route)
+            .unwrap_or_default()
+    }
+
+    /// Whether the guards keep patrolling forever, as at least one of them walks in a loop
+    pub fn joint_loops(&self) -> bool {
+        self.trajectories()
+            .iter()
+            .any(|trajectory| trajectory.cycle_start.is_some())
+    }
+
+    /// The first time two guards stand on the same cell, if they ever do.
+    ///
+    /// Guards walking in loops are checked for every time they'll meet, without simulating all of them.
+    pub fn first_collision(&self) -> Option<Collision> {
+        let trajectories = self.trajectories();
+        let settled = trajectories.iter().map(Trajectory::settled_at).max()?;
+
+        // Until every guard has settled, just compare their positions at every step
+        for time in 0..settled {
+            let mut positions = HashMap::new();
+
+            for (guard, trajectory) in trajectories.iter().enumerate() {
+                let Some(position) = trajectory.at(time) else {
+                    continue;
+                };
+
+                if let Some(&other) = positions.get(&position) {
+                    return Some(Collision {
+                        time,
+                        position,
+                        guards: (other, guard),
+                    });
+                }
+
+                positions.insert(position, guard);
+            }
+        }
+
+        // After that, only looping guards are left, so every pair of loops can be solved for when they meet
+        let mut first: Option<Collision> = None;
+
+        for (i, a) in trajectories.iter().enumerate() {
+            for (j, b) in trajectories.iter().enumerate().skip(i + 1) {
+                let Some(collision) = Self::loops_meet(a, b, settled) else {
+                    continue;
+                };
+
+                if first.is_none_or(|first| collision.0 < first.time) {
+                    first = Some(Collision {
+                        time: collision.0,
+                        position: collision.1,
+                        guards: (i, j),
+                    });
+                }
+            }
+        }
+
+        first
+    }
+
+    /// The route of every guard, as the cells it stands on
+    fn guard_routes(&self) -> impl Iterator<Item = impl Iterator<Item = Coordinate> + '_> + '_ {
+        (0..self.guards.len()).filter_map(|guard| {
+            let walk = self.walk_guard(guard)?;
+            let start = std::iter::once(self.guards[guard].0);
+            let moves = walk
+                .filter(|state| matches!(state.event, Event::Moved | Event::Teleported))
+                .map(|state| state.position);
+
+            Some(start.chain(moves))
+        })
+    }
+
+    fn trajectories(&self) -> Vec<Trajectory> {
+        (0..self.guards.len())
+            .filter_map(|guard| Some(Trajectory::new(self.walk_guard(guard)?)))
+            .collect()
+    }
+
+    /// The first time from `from` onwards that two looping guards stand on the same cell
+    fn loops_meet(a: &Trajectory, b: &Trajectory, from: usize) -> Option<(usize, Coordinate)> {
+        let (cycle_a, cycle_b) = (a.cycle()?, b.cycle()?);
+        let (start_a, start_b) = (a.cycle_start?, b.cycle_start?);
+
+        let mut offsets_b: HashMap<Coordinate, Vec<usize>> = HashMap::new();
+        for (offset, &position) in cycle_b.iter().enumerate() {
+            offsets_b.entry(position).or_default().push(offset);
+        }
+
+        cycle_a
+            .iter()
+            .enumerate()
+            .filter_map(|(offset_a, position)| Some((offset_a, position, offsets_b.get(position)?)))
+            .flat_map(|(offset_a, &position, offsets)| {
+                offsets.iter().filter_map(move |&offset_b| {
+                    // Guard A is on the cell at times start_a + offset_a + k * len_a, and B at start_b + offset_b + k * len_b
+                    let time = earliest_common_time(
+                        (start_a + offset_a, cycle_a.len()),
+                        (start_b + offset_b, cycle_b.len()),
+                        from,
+                    )?;
+                    Some((time, position))
+                })
+            })
+            .min_by_key(|&(time, _)| time)
+    }
+}
+
+/// The earliest time from `from` onwards that's congruent to both residues, by the chinese remainder theorem
+fn earliest_common_time(a: (usize, usize), b: (usize, usize), from: usize) -> Option<usize> {
+    let ((r1, m1), (r2, m2)) = ((a.0 as i128, a.1 as i128), (b.0 as i128, b.1 as i128));
+    let (g, p, _) = extended_gcd(m1, m2);
+
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+
+    let lcm = m1 / g * m2;
+    let step = ((r2 - r1) / g * p).rem_euclid(m2 / g);
+    let time = (r1 + m1 * step).rem_euclid(lcm);
+    let from = from as i128;
+
+    let behind = (from - time).max(0);
+    let time = time + (behind + lcm - 1) / lcm * lcm;
+    Some(time as usize)
+}
+
+/// Returns `(gcd, x, y)` such that `a * x + b * y = gcd`
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+
+    let (g, x, y) = extended_gcd(b, a % b);
+    (g, y, x - a / b * y)
+}
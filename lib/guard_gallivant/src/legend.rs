@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use crate::{Coordinate, Direction, Map, Tile};
+
+/// Why a map couldn't be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The map has no lines
+    Empty,
+    /// The character isn't in the legend
+    UnknownCharacter(char),
+    /// A line is not as wide as the first line of the map
+    UnevenWidth { expected: usize, found: usize },
+    /// The map has no guard
+    NoGuard,
+    /// A teleporter with the given id doesn't have exactly one partner
+    UnpairedTeleporter(usize),
+    /// The periodic obstacles take too many steps to all be back in the same state
+    PeriodOverflow,
+}
+
+/// Failed to parse a map.
+///
+/// Lines and columns start from 1, and are 0 for errors about the map as a whole.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl ParseError {
+    pub(crate) fn whole_map(kind: ParseErrorKind) -> Self {
+        Self {
+            line: 0,
+            column: 0,
+            kind,
+        }
+    }
+}
+
+/// Which characters of a map are which tiles, and which are guards facing which direction.
+///
+/// Defaults to the puzzle's legend: `.` is ground, `#` is an obstacle, and `^`, `>`, `v` and `<` are guards.
+#[derive(Debug, Clone)]
+pub struct Legend {
+    tiles: HashMap<char, Tile>,
+    guards: HashMap<char, Direction>,
+}
+
+impl Default for Legend {
+    fn default() -> Self {
+        Self {
+            tiles: HashMap::from([('.', Tile::Ground), ('#', Tile::Obstacle)]),
+            guards: Direction::CARDINAL.map(|d| (d.symbol(), d)).into(),
+        }
+    }
+}
+
+impl Legend {
+    /// Maps the character to a tile, replacing what it was mapped to before
+    pub fn with_tile(mut self, symbol: char, tile: Tile) -> Self {
+        self.guards.remove(&symbol);
+        self.tiles.insert(symbol, tile);
+        self
+    }
+
+    /// Maps the character to a guard facing the direction, standing on ground
+    pub fn with_guard(mut self, symbol: char, direction: Direction) -> Self {
+        self.tiles.remove(&symbol);
+        self.guards.insert(symbol, direction);
+        self
+    }
+
+    /// Parses a map, along with the starting position and direction of every guard on it.
+    ///
+    /// Blank lines are skipped, and whitespace around lines is ignored.
+    pub(crate) fn parse(&self, s: &str) -> Result<(Map, Vec<(Coordinate, Direction)>), ParseError> {
+        let mut environment: Vec<Vec<Tile>> = Vec::new();
+        let mut guards = Vec::new();
+        // The line and indentation of every row, to find the position of errors in the map itself
+        let mut rows = Vec::new();
+
+        for (line_idx, line) in s.lines().enumerate() {
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let indent = line[..line.len() - line.trim_start().len()].chars().count();
+            let error = |column: usize, kind| ParseError {
+                line: line_idx + 1,
+                column: indent + column + 1,
+                kind,
+            };
+
+            let row = environment.len();
+            let tiles = trimmed
+                .chars()
+                .enumerate()
+                .map(|(col, c)| {
+                    if let Some(&tile) = self.tiles.get(&c) {
+                        return Ok(tile);
+                    }
+
+                    let &direction = self
+                        .guards
+                        .get(&c)
+                        .ok_or_else(|| error(col, ParseErrorKind::UnknownCharacter(c)))?;
+                    guards.push((Coordinate(row, col), direction));
+
+                    Ok(Tile::Ground)
+                })
+                .collect::<Result<Vec<Tile>, ParseError>>()?;
+
+            if let Some(expected) = environment.first().map(|first| first.len()) {
+                if tiles.len() != expected {
+                    let found = tiles.len();
+                    return Err(error(
+                        expected.min(found),
+                        ParseErrorKind::UnevenWidth { expected, found },
+                    ));
+                }
+            }
+
+            environment.push(tiles);
+            rows.push((line_idx + 1, indent));
+        }
+
+        let width = environment.first().map_or(0, |first| first.len());
+
+        if width == 0 {
+            return Err(ParseError::whole_map(ParseErrorKind::Empty));
+        }
+
+        let map = Map::new(environment).map_err(|(Coordinate(row, col), kind)| ParseError {
+            line: rows[row].0,
+            column: rows[row].1 + col + 1,
+            kind,
+        })?;
+
+        Ok((map, guards))
+    }
+}
@@ -1,17 +1,92 @@
-use std::collections::HashSet;
-use std::iter::Cycle;
-use std::slice::Iter;
+mod guards;
+mod legend;
+mod loops;
+mod obstacles;
+mod render;
+mod rules;
+mod walk;
+
+pub use guards::{Collision, Patrol};
+pub use legend::{Legend, ParseError, ParseErrorKind};
+pub use render::{Frames, Rendering};
+pub use rules::{Movement, Rules, Turn};
+pub use walk::{Event, GuardWalk, State};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
 
-const DIRECTIONS: [(isize, isize); 4] = [
-    (-1, 0), // Up
-    (0, 1),  // Right
-    (1, 0),  // Down
-    (0, -1), // Left
+const DIRECTIONS: [(isize, isize); 8] = [
+    (-1, 0),  // Up
+    (-1, 1),  // UpRight
+    (0, 1),   // Right
+    (1, 1),   // DownRight
+    (1, 0),   // Down
+    (1, -1),  // DownLeft
+    (0, -1),  // Left
+    (-1, -1), // UpLeft
 ];
 
+/// Directions in clockwise order, where every step is a 45 degree turn
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
-pub struct Coordinate(usize, usize);
+pub enum Direction {
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Direction {
+    const ALL: [Direction; 8] = [
+        Self::Up,
+        Self::UpRight,
+        Self::Right,
+        Self::DownRight,
+        Self::Down,
+        Self::DownLeft,
+        Self::Left,
+        Self::UpLeft,
+    ];
+    const CARDINAL: [Direction; 4] = [Self::Up, Self::Right, Self::Down, Self::Left];
+
+    pub const fn offset(&self) -> (isize, isize) {
+        DIRECTIONS[*self as usize]
+    }
+
+    pub const fn turn_right(&self) -> Self {
+        self.rotate(2)
+    }
+
+    /// Turns clockwise in steps of 45 degrees
+    const fn rotate(&self, eighths: usize) -> Self {
+        Self::ALL[(*self as usize + eighths) % Self::ALL.len()]
+    }
+
+    pub const fn is_cardinal(&self) -> bool {
+        (*self as usize).is_multiple_of(2)
+    }
+
+    /// The symbol of a guard facing this direction
+    pub const fn symbol(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::UpRight => '↗',
+            Direction::Right => '>',
+            Direction::DownRight => '↘',
+            Direction::Down => 'v',
+            Direction::DownLeft => '↙',
+            Direction::Left => '<',
+            Direction::UpLeft => '↖',
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+/// A (row, column) position in the lab
+pub struct Coordinate(pub usize, pub usize);
 
 impl Coordinate {
     fn transform(&self, x: isize, y: isize) -> Option<Self> {
@@ -22,248 +97,915 @@ impl Coordinate {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Tile {
     Ground,
     Obstacle,
+    /// Ground that can only be entered while walking in the given direction, and blocks the guard otherwise
+    OneWay(Direction),
+    /// Ground that sends the guard to the other teleporter with the same id, keeping its direction
+    Teleporter(usize),
+    /// An obstacle that's there for `period` steps and gone for the next `period` steps,
+    /// starting `offset` steps into that cycle
+    Periodic {
+        period: usize,
+        offset: usize,
+    },
 }
 
-impl From<char> for Tile {
-    fn from(value: char) -> Self {
-        match value {
-            '.' | '^' => Tile::Ground,
-            '#' => Tile::Obstacle,
-            _ => unreachable!("Invalid character"),
-        }
+impl Tile {
+    /// The amount of steps after which a periodic obstacle is back in the same state,
+    /// or `None` if it doesn't fit in a `usize`
+    pub(crate) fn cycle(period: usize) -> Option<usize> {
+        period.max(1).checked_mul(2)
     }
 }
 
-pub enum WalkResult {
-    Ok(Coordinate),
-    Obstacle,
-    OutOfBounds,
+impl TryFrom<char> for Tile {
+    type Error = char;
+
+    /// Parses the puzzle's tiles, giving back any other character
+    fn try_from(value: char) -> Result<Self, Self::Error> {
+        match value {
+            '.' => Ok(Tile::Ground),
+            '#' => Ok(Tile::Obstacle),
+            _ => Err(value),
+        }
+    }
 }
 
 pub struct Map {
     height: usize,
     width: usize,
     environment: Vec<Vec<Tile>>,
+    /// Where each teleporter sends the guard
+    teleports: HashMap<Coordinate, Coordinate>,
 }
 
 impl Map {
-    fn walk_from(&self, coordinate: Coordinate, direction: &(isize, isize)) -> WalkResult {
-        let Some(coordinate) = coordinate.transform(direction.0, direction.1) else {
-            return WalkResult::OutOfBounds;
-        };
+    /// Creates a map, pairing up its teleporters and checking that its periodic obstacles are
+    /// back in the same state after a number of steps that fits in a `usize`.
+    /// Errors are returned along with the tile causing them.
+    fn new(environment: Vec<Vec<Tile>>) -> Result<Self, (Coordinate, ParseErrorKind)> {
+        // By id, so the error is the same for every run
+        let mut teleporters: BTreeMap<usize, Vec<Coordinate>> = BTreeMap::new();
+        let mut period = 1;
+
+        for (row, line) in environment.iter().enumerate() {
+            for (col, tile) in line.iter().enumerate() {
+                match *tile {
+                    Tile::Teleporter(id) => teleporters
+                        .entry(id)
+                        .or_default()
+                        .push(Coordinate(row, col)),
+                    Tile::Periodic { period: p, .. } => {
+                        period = Tile::cycle(p)
+                            .and_then(|cycle| lcm(period, cycle))
+                            .ok_or((Coordinate(row, col), ParseErrorKind::PeriodOverflow))?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut teleports = HashMap::new();
 
-        if coordinate.0 >= self.height || coordinate.1 >= self.width {
-            return WalkResult::OutOfBounds;
+        for (id, pair) in teleporters {
+            let [a, b] = pair[..] else {
+                // Either the only teleporter with this id, or the first one too many
+                let tile = *pair.get(2).unwrap_or(&pair[0]);
+                return Err((tile, ParseErrorKind::UnpairedTeleporter(id)));
+            };
+
+            teleports.insert(a, b);
+            teleports.insert(b, a);
         }
 
-        if self.environment[coordinate.0][coordinate.1] == Tile::Obstacle {
-            return WalkResult::Obstacle;
+        Ok(Self {
+            height: environment.len(),
+            width: environment.first().map_or(0, |first| first.len()),
+            environment,
+            teleports,
+        })
+    }
+
+    fn tile(&self, coordinate: Coordinate) -> Tile {
+        self.environment[coordinate.0][coordinate.1]
+    }
+
+    fn is_obstacle(&self, row: usize, col: usize) -> bool {
+        self.environment[row][col] == Tile::Obstacle
+    }
+
+    /// Whether the map only has ground and obstacles, like in the puzzle
+    fn is_plain(&self) -> bool {
+        self.environment
+            .iter()
+            .flatten()
+            .all(|tile| matches!(tile, Tile::Ground | Tile::Obstacle))
+    }
+
+    /// Whether the cell can't be entered walking in the given direction, after the given amount of steps
+    fn blocks(&self, coordinate: Coordinate, direction: Direction, time: usize) -> bool {
+        match self.tile(coordinate) {
+            Tile::Ground | Tile::Teleporter(_) => false,
+            Tile::Obstacle => true,
+            Tile::OneWay(allowed) => allowed != direction,
+            Tile::Periodic { period, offset } => {
+                let period = period.max(1) as u128;
+                (time as u128 + offset as u128) % (2 * period) < period
+            }
         }
+    }
 
-        WalkResult::Ok(coordinate)
+    /// The next coordinate in the given direction, if it's inside the map
+    fn step(&self, coordinate: Coordinate, direction: Direction) -> Option<Coordinate> {
+        let (x, y) = direction.offset();
+        let next = coordinate.transform(x, y)?;
+
+        (next.0 < self.height && next.1 < self.width).then_some(next)
     }
 }
 
-impl FromStr for Map {
-    type Err = ();
+/// The least common multiple, or `None` if it doesn't fit in a `usize`
+pub(crate) fn lcm(a: usize, b: usize) -> Option<usize> {
+    let gcd = |mut a: usize, mut b: usize| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
 
-    fn from_str(s: &str) -> Result<Map, ()> {
-        let environment: Vec<Vec<Tile>> = s
-            .lines()
-            .map(|line| line.trim().chars().map(Tile::from).collect::<Vec<Tile>>())
-            .collect();
+    (a / gcd(a, b)).checked_mul(b)
+}
 
-        let height = environment.len();
-        let width = environment.first().map(|f| f.len()).expect("No map");
+impl FromStr for Map {
+    type Err = ParseError;
 
-        Ok(Self {
-            height,
-            width,
-            environment,
-        })
+    /// Parses a map with the default legend, treating guards as ground
+    fn from_str(s: &str) -> Result<Map, Self::Err> {
+        Legend::default().parse(s).map(|(map, _)| map)
     }
 }
 
 pub struct Lab {
     map: Map,
+    /// The first guard in reading order, which single guard queries follow
     start_at: Coordinate,
+    start_direction: Direction,
+    /// Every guard in reading order, including the first one
+    guards: Vec<(Coordinate, Direction)>,
+    rules: Rules,
 }
 
 impl Lab {
-    pub fn find_guard_route_visits(&self) -> usize {
-        let mut visits = HashSet::new();
-        let mut cycle = DIRECTIONS.iter().cycle(); // Cycle directions for when we hit obstacles
-        let mut guard_pos = self.start_at;
+    pub fn with_rules(mut self, rules: Rules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Parses a lab with one or more guards, using the given legend
+    pub fn parse(s: &str, legend: &Legend) -> Result<Self, ParseError> {
+        let (map, guards) = legend.parse(s)?;
 
-        let Some(next_direction) = cycle.next() else {
-            unreachable!("Failed to get next direction!");
+        let Some(&(start_at, start_direction)) = guards.first() else {
+            return Err(ParseError::whole_map(ParseErrorKind::NoGuard));
         };
 
-        let mut direction = *next_direction;
+        Ok(Self {
+            map,
+            start_at,
+            start_direction,
+            guards,
+            rules: Rules::default(),
+        })
+    }
 
-        loop {
-            visits.insert(guard_pos);
+    /// Simulates the guard's patrol one step at a time
+    pub fn walk(&self) -> GuardWalk<'_> {
+        GuardWalk::new(self)
+    }
 
-            if !self.walk_step(&mut cycle, &mut guard_pos, &mut direction, None) {
-                break;
-            }
+    pub fn find_guard_route_visits(&self) -> usize {
+        let moves = self
+            .walk()
+            .filter(|state| matches!(state.event, Event::Moved | Event::Teleported))
+            .map(|state| state.position);
+
+        std::iter::once(self.start_at)
+            .chain(moves)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+}
+
+impl FromStr for Lab {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Lab, Self::Err> {
+        Self::parse(s, &Legend::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = r#"
+        ....#.....
+        .........#
+        ..........
+        ..#.......
+        .......#..
+        ..........
+        .#..^.....
+        ........#.
+        #.........
+        ......#..."#;
+
+    #[test]
+    fn solution_1() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        assert_eq!(lab.find_guard_route_visits(), 41);
+    }
+
+    #[test]
+    fn solution_2() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        assert_eq!(lab.find_route_loops(), 6)
+    }
+
+    #[test]
+    fn parallel_loops() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        let expected = lab.looping_obstacles();
+
+        assert_eq!(expected.len(), 6);
+
+        for threads in 2..=8 {
+            assert_eq!(lab.looping_obstacles_parallel(threads), expected);
+            assert_eq!(lab.find_route_loops_parallel(threads), 6);
         }
+    }
 
-        visits.len()
-    }
-
-    fn walk_step(
-        &self,
-        cycle: &mut Cycle<Iter<(isize, isize)>>,
-        guard_pos: &mut Coordinate,
-        direction: &mut (isize, isize),
-        added_obstacle: Option<Coordinate>,
-    ) -> bool {
-        match self.map.walk_from(*guard_pos, direction) {
-            WalkResult::Ok(coordinate) => {
-                // If we simulate an added obstacle, we need to change directions if we hit it instead of giving a new coordinate
-                if let Some(obstacle) = added_obstacle {
-                    if obstacle == coordinate {
-                        let Some(next_direction) = cycle.next() else {
-                            unreachable!("Failed to get next direction!");
-                        };
-                        *direction = *next_direction;
-                        return true;
-                    }
-                }
+    #[test]
+    fn obstacle_loops() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        let obstacles =
+            [(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)].map(|(r, c)| Coordinate(r, c));
 
-                *guard_pos = coordinate;
-            }
-            WalkResult::Obstacle => {
-                let Some(next_direction) = cycle.next() else {
-                    unreachable!("Failed to get next direction!");
-                };
-                *direction = *next_direction;
-            }
-            WalkResult::OutOfBounds => {
-                return false;
+        assert_eq!(lab.looping_obstacles(), obstacles);
+        assert_eq!(lab.loop_with_obstacle(Coordinate(0, 0)), None);
+
+        let loops = lab.obstacle_loops();
+        assert_eq!(loops.len(), obstacles.len());
+
+        for (obstacle, states) in loops {
+            assert!(obstacles.contains(&obstacle));
+
+            // Each state must lead to the next, wrapping around to the start of the loop
+            for (i, &(pos, dir)) in states.iter().enumerate() {
+                let next = states[(i + 1) % states.len()];
+                let ahead = lab.map.step(pos, dir).expect("Loop left the lab");
+
+                if ahead == obstacle || lab.map.is_obstacle(ahead.0, ahead.1) {
+                    assert_eq!(next, (pos, dir.turn_right()));
+                } else {
+                    assert_eq!(next, (ahead, dir));
+                }
             }
         }
+    }
 
-        true
+    #[test]
+    fn guard_walk() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        let states: Vec<State> = lab.walk().take(6).collect();
+
+        assert_eq!(
+            states[4],
+            State {
+                position: Coordinate(1, 4),
+                direction: Direction::Up,
+                phase: 0,
+                time: 5,
+                event: Event::Moved,
+            }
+        );
+        assert_eq!(states[5].event, Event::Turned);
+        assert_eq!(states[5].direction, Direction::Right);
+
+        let last = lab.walk().last().expect("Guard didn't walk");
+        assert_eq!(last.event, Event::Exited);
+        assert_eq!(last.position, Coordinate(9, 7));
+
+        let last = lab.walk().with_obstacle(Coordinate(6, 3)).last();
+        assert_eq!(last.map(|state| state.event), Some(Event::Looped));
     }
 
-    pub fn find_route_loops(&self) -> usize {
-        let mut looping_obstacles = HashSet::new();
-        let mut cycle = DIRECTIONS.iter().cycle();
+    #[test]
+    fn route_visits_in_loop() {
+        let lab = Lab::from_str(".#...\n....#\n.^...\n#....\n...#.").expect("Failed to parse lab");
+        assert_eq!(lab.find_guard_route_visits(), 8);
+    }
 
-        let mut guard_pos = self.start_at;
+    #[test]
+    fn render_route() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        let expected = [
+            "....#.....",
+            "....+---+#",
+            "....|...|.",
+            "..#.|...|.",
+            "....|..#|.",
+            "....|...|.",
+            ".#.O^---+.",
+            "........#.",
+            "#.........",
+            "......#...",
+        ];
+
+        assert_eq!(
+            lab.render(&[Coordinate(6, 3)]).to_string(),
+            expected.join("\n") + "\n"
+        );
+
+        let frames: Vec<Rendering> = lab.frames(&[]).collect();
+        assert_eq!(frames.len(), lab.walk().count());
+        assert_eq!(frames[0].to_string().lines().nth(5), Some("....^....."));
+
+        let looping = lab.render_looping_obstacles().to_string();
+        assert_eq!(looping.matches('O').count(), 6);
+
+        let mut image = Vec::new();
+        lab.render(&[])
+            .write_ppm(&mut image, 2)
+            .expect("Failed to write image");
+        assert!(image.starts_with(b"P6\n20 20\n255\n"));
+        assert_eq!(image.len(), b"P6\n20 20\n255\n".len() + 20 * 20 * 3);
+    }
+
+    #[test]
+    fn start_directions() {
+        let lab = Lab::from_str("...\n.>#\n...").expect("Failed to parse lab");
+        let last = lab.walk().last().expect("Guard didn't walk");
+
+        assert_eq!(last.position, Coordinate(2, 1));
+        assert_eq!(last.direction, Direction::Down);
+        assert_eq!(lab.find_guard_route_visits(), 2);
+    }
 
-        let Some(next_direction) = cycle.next() else {
-            unreachable!("Failed to get next direction!");
+    #[test]
+    fn parse_errors() {
+        let error = Lab::from_str("\n  ..#\n  .x^").err();
+        assert_eq!(
+            error,
+            Some(ParseError {
+                line: 3,
+                column: 4,
+                kind: ParseErrorKind::UnknownCharacter('x'),
+            })
+        );
+
+        let error = Lab::from_str("...\n.^").err().map(|e| (e.line, e.kind));
+        let kind = ParseErrorKind::UnevenWidth {
+            expected: 3,
+            found: 2,
         };
+        assert_eq!(error, Some((2, kind)));
 
-        let mut direction = *next_direction;
+        let error = Lab::from_str("...\n...").err().map(|e| e.kind);
+        assert_eq!(error, Some(ParseErrorKind::NoGuard));
 
-        loop {
-            let next_coord = match self.map.walk_from(guard_pos, &direction) {
-                WalkResult::Ok(coord) => coord,
-                WalkResult::OutOfBounds => break, // We're done checking the different routes
-                WalkResult::Obstacle => {
-                    // Get and set next direction and continue looping
-                    let Some(next_direction) = cycle.next() else {
-                        unreachable!("Failed to get next direction!");
-                    };
+        let error = Lab::from_str(" \n ").err().map(|e| e.kind);
+        assert_eq!(error, Some(ParseErrorKind::Empty));
+    }
 
-                    direction = *next_direction;
+    #[test]
+    fn custom_legend() {
+        let legend = Legend::default()
+            .with_tile('x', Tile::Obstacle)
+            .with_tile('#', Tile::Ground)
+            .with_guard('G', Direction::Left);
+        let lab = Lab::parse("x#G\n###", &legend).expect("Failed to parse lab");
+
+        assert_eq!(lab.start_direction, Direction::Left);
+        assert_eq!(lab.find_guard_route_visits(), 2);
+        assert_eq!(Tile::try_from('x'), Err('x'));
+    }
 
-                    continue;
-                }
-            };
+    #[test]
+    fn turning_rules() {
+        let corridor = "#\n.\n^\n#";
+        let lab = Lab::from_str(corridor).expect("Failed to parse lab");
+        assert_eq!(lab.walk().last().map(|s| s.event), Some(Event::Exited));
+
+        let lab = lab.with_rules(Rules::default().with_turn(Turn::Reverse));
+        assert_eq!(lab.walk().last().map(|s| s.event), Some(Event::Looped));
+        assert_eq!(lab.find_guard_route_visits(), 2);
+
+        let rules = Rules::default().with_movement(Movement::Eight);
+        let lab = Lab::from_str(".#.\n.^.\n...")
+            .expect("Failed to parse lab")
+            .with_rules(rules);
+        let last = lab.walk().last().expect("Guard didn't walk");
+
+        assert_eq!(last.event, Event::Exited);
+        assert_eq!(
+            (last.position, last.direction),
+            (Coordinate(0, 2), Direction::UpRight)
+        );
+    }
 
-            let can_set_obstacle =
-                !looping_obstacles.contains(&next_coord) && next_coord != self.start_at;
+    #[test]
+    fn multiple_guards() {
+        let lab = Lab::from_str("v..\n...\n^.<").expect("Failed to parse lab");
+
+        assert_eq!(lab.guards().len(), 3);
+        assert_eq!(lab.visited_by_any().len(), 5);
+        assert_eq!(lab.visited_by_all(), HashSet::from([Coordinate(2, 0)]));
+        assert!(!lab.joint_loops());
+        assert_eq!(
+            lab.first_collision(),
+            Some(Collision {
+                time: 1,
+                position: Coordinate(1, 0),
+                guards: (0, 1),
+            })
+        );
+
+        let ticks: Vec<Vec<Option<State>>> = lab.patrol().collect();
+        assert_eq!(ticks.len(), 3);
+        assert!(ticks[2]
+            .iter()
+            .all(|state| state.is_none_or(|s| s.event == Event::Exited)));
+    }
 
-            if can_set_obstacle {
-                let mut dir_cycle = DIRECTIONS.iter().cycle(); // Clone this, as we need to be at the same cycle.
+    #[test]
+    fn looping_guards_collide() {
+        let map = "...#.....\n...v.....\n#>......#\n.........\n...#.....";
+        let lab = Lab::from_str(map)
+            .expect("Failed to parse lab")
+            .with_rules(Rules::default().with_turn(Turn::Reverse));
+
+        assert!(lab.joint_loops());
+        assert_eq!(
+            lab.first_collision(),
+            Some(Collision {
+                time: 16,
+                position: Coordinate(2, 3),
+                guards: (0, 1),
+            })
+        );
+
+        let ticks = lab.patrol().take(16).last().expect("Guards didn't walk");
+        let positions: Vec<_> = ticks.iter().flatten().map(|s| s.position).collect();
+        assert_eq!(positions, [Coordinate(2, 3), Coordinate(2, 3)]);
+    }
 
-                // Get and set next direction and continue looping
-                let Some(next_direction) = dir_cycle.next() else {
-                    unreachable!("Failed to get next direction!");
-                };
+    fn extended_legend() -> Legend {
+        Legend::default()
+            .with_tile('u', Tile::OneWay(Direction::Up))
+            .with_tile('d', Tile::OneWay(Direction::Down))
+            .with_tile('l', Tile::OneWay(Direction::Left))
+            .with_tile('r', Tile::OneWay(Direction::Right))
+            .with_tile('a', Tile::Teleporter(0))
+            .with_tile('b', Tile::Teleporter(1))
+            .with_tile(
+                'p',
+                Tile::Periodic {
+                    period: 1,
+                    offset: 0,
+                },
+            )
+            .with_tile(
+                'q',
+                Tile::Periodic {
+                    period: 2,
+                    offset: 2,
+                },
+            )
+    }
 
-                let obstacle = next_coord;
-                let mut guard = self.start_at;
-                let mut dir = *next_direction;
+    #[test]
+    fn extended_tiles() {
+        let legend = extended_legend();
+        let visits = |map: &str| {
+            let lab = Lab::parse(map, &legend).expect("Failed to parse lab");
+            (
+                lab.find_guard_route_visits(),
+                lab.walk().last().map(|s| s.event),
+            )
+        };
 
-                let mut visits = HashSet::new();
+        assert_eq!(visits("#\nd\n^"), (1, Some(Event::Exited)));
+        assert_eq!(visits("#\nu\n^"), (2, Some(Event::Exited)));
+        assert_eq!(visits(".a.\n...\n...\n.^.\n.a."), (4, Some(Event::Looped)));
+        assert_eq!(visits("#p#\n#^#"), (1, Some(Event::Exited)));
+        assert_eq!(visits(".\nq\n^"), (3, Some(Event::Exited)));
+
+        let error = Lab::parse("..a\n.^.\nb.a\n", &legend).err();
+        assert_eq!(
+            error,
+            Some(ParseError {
+                line: 3,
+                column: 1,
+                kind: ParseErrorKind::UnpairedTeleporter(1),
+            })
+        );
+
+        // With several unpaired teleporters, the one with the smallest id is reported
+        let error = Lab::parse("b.a\n.^.\n", &legend).err();
+        assert_eq!(
+            error,
+            Some(ParseError {
+                line: 1,
+                column: 3,
+                kind: ParseErrorKind::UnpairedTeleporter(0),
+            })
+        );
+
+        let lab = Lab::parse("#a#\n.^.\nra.", &legend).expect("Failed to parse lab");
+        assert_eq!(lab.render(&[]).to_string(), "#@#\n.^.\n⇒@.\n");
+    }
 
-                // Simulate new route
-                loop {
-                    // If we try to visit something already visited, with the same direction, we have found a loop
-                    if visits.contains(&(guard, dir)) {
-                        looping_obstacles.insert(next_coord);
-                        break;
-                    } else {
-                        visits.insert((guard, dir));
-                    }
+    #[test]
+    fn coprime_periods() {
+        let legend = [7, 11, 13, 17, 19, 23, 29].into_iter().zip('0'..).fold(
+            Legend::default(),
+            |legend, (period, symbol)| {
+                legend.with_tile(symbol, Tile::Periodic { period, offset: 0 })
+            },
+        );
+
+        // The guard only ever runs into the first periodic obstacle, so the others don't slow down finding its loop
+        let mut grid = vec![vec!['.'; 40]; 40];
+        for (row, col) in [(9, 10), (10, 31), (31, 30), (30, 9)] {
+            grid[row][col] = '#';
+        }
+        for (col, symbol) in (20..).zip('0'..='6') {
+            grid[if symbol == '0' { 10 } else { 2 }][col] = symbol;
+        }
+        grid[25][10] = '^';
+
+        let map = map_text(&grid);
+        let lab = Lab::parse(&map, &legend).expect("Failed to parse lab");
+
+        let states: Vec<State> = lab.walk().collect();
+        assert_eq!(states.last().map(|s| s.event), Some(Event::Looped));
+        assert_eq!(states.len(), 111);
+        assert_eq!(lab.find_guard_route_visits(), 80);
+
+        let error = Lab::parse(
+            "0.\n.^",
+            &Legend::default().with_tile(
+                '0',
+                Tile::Periodic {
+                    period: usize::MAX,
+                    offset: 0,
+                },
+            ),
+        );
+        assert_eq!(
+            error.err(),
+            Some(ParseError {
+                line: 1,
+                column: 1,
+                kind: ParseErrorKind::PeriodOverflow,
+            })
+        );
+    }
 
-                    if !self.walk_step(&mut dir_cycle, &mut guard, &mut dir, Some(obstacle)) {
-                        break;
-                    }
+    #[test]
+    fn obstacle_queries() {
+        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
+        let trap = lab.min_trapping_obstacles(2).expect("No trap found");
+
+        assert_eq!(trap.len(), 1);
+        assert!(lab.looping_obstacles().contains(&trap[0]));
+        assert_eq!(lab.removable_obstacles(), []);
+
+        let lab = Lab::from_str(&EXAMPLE.replace("\n        ......#...", "\n        #.....#..."))
+            .expect("Failed to parse lab");
+        assert_eq!(lab.removable_obstacles(), [Coordinate(9, 0)]);
+
+        let lab = Lab::from_str("...\n...\n.^.").expect("Failed to parse lab");
+        assert_eq!(lab.min_trapping_obstacles(3), None);
+
+        let lab = Lab::from_str("..\n^.").expect("Failed to parse lab");
+        assert_eq!(lab.max_visits_obstacle(), Some((Coordinate(0, 0), 2)));
+        assert_eq!(
+            Lab::from_str("^")
+                .expect("Failed to parse lab")
+                .max_visits_obstacle(),
+            None
+        );
+    }
+
+    /// Small xorshift generator, so the random maps are reproducible without extra dependencies
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// A grid of ground where about one in every `density` cells is an obstacle
+    fn random_map(
+        rng: &mut XorShift,
+        height: usize,
+        width: usize,
+        density: usize,
+    ) -> Vec<Vec<char>> {
+        (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| if rng.next(density) == 0 { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn map_text(grid: &[Vec<char>]) -> String {
+        grid.iter()
+            .map(|line| line.iter().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Places an obstacle on every free cell in turn, simulating the whole walk one step at a time.
+    /// Returns `None` if the guard walks in a loop without any added obstacles.
+    fn brute_force_loops(map: &str) -> Option<usize> {
+        let grid: Vec<Vec<char>> = map.lines().map(|l| l.chars().collect()).collect();
+        let (start, direction) = grid
+            .iter()
+            .enumerate()
+            .find_map(|(row, line)| {
+                let col = line.iter().position(|&c| "^>v<".contains(c))?;
+                let direction = Direction::CARDINAL
+                    .into_iter()
+                    .find(|d| d.symbol() == line[col])?;
+                Some(((row, col), direction))
+            })
+            .expect("No guard found");
+
+        let loops = |obstacle: (usize, usize)| {
+            let mut visits = HashSet::new();
+            let (mut pos, mut dir) = (start, direction);
+
+            while visits.insert((pos, dir)) {
+                let (x, y) = dir.offset();
+                let next = (pos.0 as isize + x, pos.1 as isize + y);
+
+                if next.0 < 0 || next.1 < 0 {
+                    return false;
+                }
+
+                let next = (next.0 as usize, next.1 as usize);
+                match grid.get(next.0).and_then(|line| line.get(next.1)) {
+                    None => return false,
+                    Some('#') => dir = dir.turn_right(),
+                    Some(_) if next == obstacle => dir = dir.turn_right(),
+                    Some(_) => pos = next,
                 }
             }
 
-            guard_pos = next_coord;
+            true
+        };
+
+        if loops(start) {
+            return None;
         }
 
-        looping_obstacles.len()
+        let count = (0..grid.len())
+            .flat_map(|row| (0..grid[row].len()).map(move |col| (row, col)))
+            .filter(|&cell| cell != start && grid[cell.0][cell.1] == '.')
+            .filter(|&cell| loops(cell))
+            .count();
+
+        Some(count)
     }
-}
 
-impl FromStr for Lab {
-    type Err = ();
+    #[test]
+    fn loops_match_brute_force() {
+        let mut rng = XorShift(0x2024_1206);
 
-    fn from_str(s: &str) -> Result<Lab, ()> {
-        let s = s.trim();
+        for _ in 0..200 {
+            let (height, width) = (3 + rng.next(10), 3 + rng.next(10));
+            let mut grid = random_map(&mut rng, height, width, 6);
+            grid[rng.next(height)][rng.next(width)] = Direction::CARDINAL[rng.next(4)].symbol();
 
-        let start_at = s
-            .lines()
-            .enumerate()
-            .filter_map(|(i, l)| Some(Coordinate(i, l.trim().match_indices('^').next()?.0)))
-            .next()
-            .expect("No guard found");
+            let map = map_text(&grid);
+            let Some(expected) = brute_force_loops(&map) else {
+                continue;
+            };
+            let lab = Lab::from_str(&map).expect("Failed to parse lab");
+
+            assert_eq!(lab.find_route_loops(), expected, "map:\n{map}");
+        }
+    }
+
+    #[test]
+    fn loops_with_rules_match_simulation() {
+        let mut rng = XorShift(0x2024_0647);
+        let turns = [Turn::Right, Turn::Left, Turn::Reverse];
+
+        for _ in 0..200 {
+            let (height, width) = (3 + rng.next(8), 3 + rng.next(8));
+            let mut grid = random_map(&mut rng, height, width, 5);
+            let start = Coordinate(rng.next(height), rng.next(width));
+            grid[start.0][start.1] = Direction::CARDINAL[rng.next(4)].symbol();
+
+            let sequence: Vec<Turn> = (0..1 + rng.next(3)).map(|_| turns[rng.next(3)]).collect();
+            let movement = [Movement::Four, Movement::Eight][rng.next(2)];
+            let rules = Rules::default()
+                .with_turn_sequence(sequence)
+                .with_movement(movement);
+
+            let map = map_text(&grid);
+            let lab = Lab::from_str(&map)
+                .expect("Failed to parse lab")
+                .with_rules(rules.clone());
+
+            if lab.walk().last().map(|s| s.event) == Some(Event::Looped) {
+                continue;
+            }
 
-        let map = Map::from_str(s)?;
+            let expected: Vec<Coordinate> = (0..height)
+                .flat_map(|row| (0..width).map(move |col| Coordinate(row, col)))
+                .filter(|&cell| cell != start && grid[cell.0][cell.1] == '.')
+                .filter(|&cell| lab.loop_with_obstacle(cell).is_some())
+                .collect();
 
-        Ok(Self { map, start_at })
+            assert_eq!(lab.looping_obstacles(), expected, "{rules:?}, map:\n{map}");
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn collisions_match_simulation() {
+        let mut rng = XorShift(0x2024_0648);
 
-    const EXAMPLE: &str = r#"
-        ....#.....
-        .........#
-        ..........
-        ..#.......
-        .......#..
-        ..........
-        .#..^.....
-        ........#.
-        #.........
-        ......#..."#;
+        for _ in 0..300 {
+            let (height, width) = (3 + rng.next(6), 3 + rng.next(6));
+            let mut grid = random_map(&mut rng, height, width, 4);
+
+            for _ in 0..2 + rng.next(2) {
+                grid[rng.next(height)][rng.next(width)] = Direction::CARDINAL[rng.next(4)].symbol();
+            }
+
+            let map = map_text(&grid);
+            let lab = Lab::from_str(&map).expect("Failed to parse lab");
+
+            // Simulate the guards in lockstep for a while, looking for two of them on the same cell
+            let limit = 5000;
+            let start = lab
+                .guards()
+                .iter()
+                .map(|&(position, _)| Some(position))
+                .collect();
+            let ticks = lab.patrol().take(limit).map(|states| {
+                states
+                    .iter()
+                    .map(|state| {
+                        state
+                            .filter(|s| s.event != Event::Exited)
+                            .map(|s| s.position)
+                    })
+                    .collect::<Vec<_>>()
+            });
+            let expected = std::iter::once(start).chain(ticks).position(
+                |positions: Vec<Option<Coordinate>>| {
+                    let positions: Vec<_> = positions.into_iter().flatten().collect();
+                    positions.len() != positions.iter().collect::<HashSet<_>>().len()
+                },
+            );
+
+            let collision = lab.first_collision().map(|c| c.time);
+
+            match expected {
+                Some(time) => assert_eq!(collision, Some(time), "map:\n{map}"),
+                None => assert!(collision.is_none_or(|time| time > limit), "map:\n{map}"),
+            }
+        }
+    }
+
+    /// Whether the guard leaves the lab within the given amount of steps, without detecting loops.
+    ///
+    /// The steps should cover every state, for every direction and state of the periodic obstacles.
+    fn exits_within(lab: &Lab, obstacle: Coordinate, steps: usize) -> bool {
+        let mut walk = lab.walk().with_obstacle(obstacle);
+
+        (0..=steps).any(|_| match walk.next() {
+            Some(state) if state.event == Event::Looped => {
+                walk.restart();
+                false
+            }
+            state => state.is_none_or(|state| state.event == Event::Exited),
+        })
+    }
 
     #[test]
-    fn solution_1() {
-        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
-        assert_eq!(lab.find_guard_route_visits(), 41);
+    fn loops_with_tiles_match_simulation() {
+        let mut rng = XorShift(0x2024_0649);
+        let legend = extended_legend();
+        let tiles = ['#', 'u', 'd', 'l', 'r', 'p', 'q'];
+
+        for _ in 0..300 {
+            let (height, width) = (3 + rng.next(7), 3 + rng.next(7));
+            let mut grid = random_map(&mut rng, height, width, 8);
+
+            for cell in grid.iter_mut().flatten().filter(|cell| **cell == '#') {
+                *cell = tiles[rng.next(tiles.len())];
+            }
+
+            for symbol in ['a', 'a', Direction::CARDINAL[rng.next(4)].symbol()] {
+                grid[rng.next(height)][rng.next(width)] = symbol;
+            }
+
+            let map = map_text(&grid);
+            let Ok(lab) = Lab::parse(&map, &legend) else {
+                // One teleporter got overwritten
+                continue;
+            };
+
+            if lab.walk().last().map(|s| s.event) == Some(Event::Looped) {
+                continue;
+            }
+
+            let start = lab.start_at;
+            let expected: Vec<Coordinate> = (0..height)
+                .flat_map(|row| (0..width).map(move |col| Coordinate(row, col)))
+                .filter(|&cell| cell != start && grid[cell.0][cell.1] == '.')
+                .filter(|&cell| !exits_within(&lab, cell, height * width * 32))
+                .collect();
+
+            assert_eq!(lab.looping_obstacles(), expected, "map:\n{map}");
+        }
     }
 
     #[test]
-    fn solution_2() {
-        let lab = Lab::from_str(EXAMPLE).expect("Failed to parse lab");
-        assert_eq!(lab.find_route_loops(), 6)
+    fn obstacle_queries_match_brute_force() {
+        let mut rng = XorShift(0x2024_0650);
+
+        for _ in 0..150 {
+            let (height, width) = (2 + rng.next(4), 2 + rng.next(4));
+            let mut grid = random_map(&mut rng, height, width, 5);
+            grid[rng.next(height)][rng.next(width)] = Direction::CARDINAL[rng.next(4)].symbol();
+
+            let map = map_text(&grid);
+            let lab = Lab::from_str(&map).expect("Failed to parse lab");
+            let free: Vec<Coordinate> = (0..height)
+                .flat_map(|row| (0..width).map(move |col| Coordinate(row, col)))
+                .filter(|&cell| grid[cell.0][cell.1] == '.')
+                .collect();
+
+            let loops = |obstacles: &[Coordinate]| {
+                lab.walk()
+                    .with_obstacles(obstacles.iter().copied())
+                    .last()
+                    .map(|s| s.event)
+                    == Some(Event::Looped)
+            };
+            let visits = |obstacle: Coordinate| {
+                let walk = lab.walk().with_obstacle(obstacle);
+                let mut cells = HashSet::from([lab.start_at]);
+                cells.extend(walk.filter(|s| s.event == Event::Moved).map(|s| s.position));
+                cells.len()
+            };
+
+            // The smallest trap of up to two obstacles, by trying every set
+            let expected = if loops(&[]) {
+                Some(0)
+            } else if free.iter().any(|&a| loops(&[a])) {
+                Some(1)
+            } else if free.iter().any(|&a| free.iter().any(|&b| loops(&[a, b]))) {
+                Some(2)
+            } else {
+                None
+            };
+
+            let trap = lab.min_trapping_obstacles(2);
+            assert_eq!(
+                trap.as_ref().map(|trap| trap.len()),
+                expected,
+                "map:\n{map}"
+            );
+            assert!(trap.is_none_or(|trap| loops(&trap)), "map:\n{map}");
+
+            if expected == Some(0) {
+                continue;
+            }
+
+            let best = free
+                .iter()
+                .filter(|&&cell| !loops(&[cell]))
+                .map(|&cell| (cell, visits(cell)))
+                .max_by_key(|&(cell, visits)| (visits, std::cmp::Reverse(cell)));
+            assert_eq!(lab.max_visits_obstacle(), best, "map:\n{map}");
+        }
     }
 }
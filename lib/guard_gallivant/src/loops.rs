@@ -0,0 +1,318 @@
+use crate::{Coordinate, Direction, Event, GuardWalk, Lab, Map, Rules, Tile};
+
+/// Where the guard ends up when walking straight from a cell, until it hits an obstacle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Jump {
+    /// The guard stops at the cell in front of an obstacle
+    Stop(Coordinate),
+    /// The guard walks out of the lab
+    Exit,
+}
+
+/// Precomputed jumps for every cell and cardinal direction, so walks can skip straight to the next turn
+struct JumpTable {
+    width: usize,
+    jumps: Vec<[Jump; 4]>,
+}
+
+impl JumpTable {
+    fn new(map: &Map) -> Self {
+        let (height, width) = (map.height, map.width);
+        let mut jumps = vec![[Jump::Exit; 4]; height * width];
+
+        // Walk each line against the direction, remembering where the last obstacle was seen
+        for row in 0..height {
+            let mut stop = Jump::Exit;
+            for col in 0..width {
+                stop = Self::fill(map, &mut jumps, width, stop, row, col, Direction::Left);
+            }
+
+            let mut stop = Jump::Exit;
+            for col in (0..width).rev() {
+                stop = Self::fill(map, &mut jumps, width, stop, row, col, Direction::Right);
+            }
+        }
+
+        for col in 0..width {
+            let mut stop = Jump::Exit;
+            for row in 0..height {
+                stop = Self::fill(map, &mut jumps, width, stop, row, col, Direction::Up);
+            }
+
+            let mut stop = Jump::Exit;
+            for row in (0..height).rev() {
+                stop = Self::fill(map, &mut jumps, width, stop, row, col, Direction::Down);
+            }
+        }
+
+        Self { width, jumps }
+    }
+
+    /// Sets the jump for a single cell, returning the jump for the next cell against the direction
+    fn fill(
+        map: &Map,
+        jumps: &mut [[Jump; 4]],
+        width: usize,
+        stop: Jump,
+        row: usize,
+        col: usize,
+        direction: Direction,
+    ) -> Jump {
+        if map.is_obstacle(row, col) {
+            // Cells further against the direction stop right before this obstacle
+            let (x, y) = direction.offset();
+            return Coordinate(row, col)
+                .transform(-x, -y)
+                .map_or(Jump::Exit, Jump::Stop);
+        }
+
+        jumps[row * width + col][direction as usize / 2] = stop;
+        stop
+    }
+
+    fn jump(&self, from: Coordinate, direction: Direction) -> Jump {
+        self.jumps[from.0 * self.width + from.1][direction as usize / 2]
+    }
+}
+
+/// Dense set of states, which can be cleared in the time it took to fill it.
+///
+/// Every cell has the given amount of layers, such as one for each direction.
+struct VisitedStates {
+    width: usize,
+    layers: usize,
+    bits: Vec<u64>,
+    set: Vec<usize>,
+}
+
+impl VisitedStates {
+    fn new(height: usize, width: usize, layers: usize) -> Self {
+        Self {
+            width,
+            layers,
+            bits: vec![0; (height * width * layers).div_ceil(64)],
+            set: Vec::new(),
+        }
+    }
+
+    /// Adds the state, returning whether it wasn't already visited
+    fn insert(&mut self, coordinate: Coordinate, layer: usize) -> bool {
+        let idx = (coordinate.0 * self.width + coordinate.1) * self.layers + layer;
+        let (word, bit) = (idx / 64, 1 << (idx % 64));
+
+        if self.bits[word] & bit != 0 {
+            return false;
+        }
+
+        if self.bits[word] == 0 {
+            self.set.push(word);
+        }
+
+        self.bits[word] |= bit;
+        true
+    }
+
+    fn clear(&mut self) {
+        self.set.drain(..).for_each(|word| self.bits[word] = 0);
+    }
+}
+
+/// A cell to place an obstacle on, along with the state of the guard right before first reaching it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Candidate {
+    guard_pos: Coordinate,
+    direction: Direction,
+    phase: usize,
+    time: usize,
+    pub(crate) obstacle: Coordinate,
+}
+
+impl Lab {
+    /// Counts the positions where a single added obstacle makes the guard walk in a loop.
+    ///
+    /// Only cells on the guard's original route can change it, and an obstacle there is only reached after
+    /// walking the route up to the first time the guard would step onto it.
+    /// So each candidate is simulated from right in front of it, jumping from turn to turn
+    /// when the guard follows the puzzle's rules on a plain map, and one step at a time otherwise.
+    pub fn find_route_loops(&self) -> usize {
+        self.looping_obstacles().len()
+    }
+
+    /// Same as [`Lab::find_route_loops`], but evaluates the candidates on the given amount of threads
+    pub fn find_route_loops_parallel(&self, threads: usize) -> usize {
+        self.looping_obstacles_parallel(threads).len()
+    }
+
+    /// The sorted positions where a single added obstacle makes the guard walk in a loop
+    pub fn looping_obstacles(&self) -> Vec<Coordinate> {
+        self.looping_obstacles_parallel(1)
+    }
+
+    /// Same as [`Lab::looping_obstacles`], but evaluates the candidates on the given amount of threads
+    pub fn looping_obstacles_parallel(&self, threads: usize) -> Vec<Coordinate> {
+        // Jumps only work for turning right between the cardinal directions, on ground and obstacles
+        let plain = self.rules == Rules::default()
+            && self.start_direction.is_cardinal()
+            && self.map.is_plain();
+        let jumps = plain.then(|| JumpTable::new(&self.map));
+        let candidates = self.loop_candidates();
+        let chunk_size = candidates.len().div_ceil(threads.max(1)).max(1);
+
+        let evaluate = |chunk: &[Candidate]| {
+            let mut visits =
+                VisitedStates::new(self.map.height, self.map.width, Direction::ALL.len());
+
+            chunk
+                .iter()
+                .filter(|candidate| match &jumps {
+                    Some(jumps) => {
+                        let loops = Self::loops_with_obstacle(jumps, &mut visits, candidate);
+                        visits.clear();
+                        loops
+                    }
+                    None => self.walk_loops_with_obstacle(candidate),
+                })
+                .map(|candidate| candidate.obstacle)
+                .collect::<Vec<_>>()
+        };
+
+        let mut obstacles: Vec<Coordinate> = match threads {
+            0 | 1 => evaluate(&candidates),
+            _ => std::thread::scope(|scope| {
+                candidates
+                    .chunks(chunk_size)
+                    .map(|chunk| scope.spawn(|| evaluate(chunk)))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("Failed to evaluate candidates"))
+                    .collect()
+            }),
+        };
+
+        obstacles.sort_unstable();
+        obstacles
+    }
+
+    /// Every looping obstacle, along with the loop the guard ends up walking because of it
+    pub fn obstacle_loops(&self) -> Vec<(Coordinate, Vec<(Coordinate, Direction)>)> {
+        self.looping_obstacles()
+            .into_iter()
+            .filter_map(|obstacle| Some((obstacle, self.loop_with_obstacle(obstacle)?)))
+            .collect()
+    }
+
+    /// Simulates the guard one step at a time with an obstacle added.
+    ///
+    /// If it walks in a loop, returns every (position, direction) state of the loop,
+    /// starting from the first state the guard repeats.
+    pub fn loop_with_obstacle(&self, obstacle: Coordinate) -> Option<Vec<(Coordinate, Direction)>> {
+        let (mut states, loop_start) = self.walk().with_obstacle(obstacle).trace();
+        Some(states.split_off(loop_start?))
+    }
+
+    /// Walks the original route, finding every cell an obstacle could be placed on to change it
+    pub(crate) fn loop_candidates(&self) -> Vec<Candidate> {
+        let mut tried = vec![false; self.map.height * self.map.width];
+        let mut candidates = Vec::new();
+        let mut guard_pos = self.start_at;
+
+        tried[guard_pos.0 * self.map.width + guard_pos.1] = true;
+
+        // If the guard already walks in a loop, the walk ends and there are no more cells to try
+        for state in self.walk() {
+            if !matches!(state.event, Event::Moved | Event::Teleported) {
+                continue;
+            }
+
+            let cell = state.position.0 * self.map.width + state.position.1;
+
+            // Obstacles can only be placed on plain ground
+            if !tried[cell] && state.event == Event::Moved {
+                tried[cell] = true;
+
+                if self.map.tile(state.position) == Tile::Ground {
+                    candidates.push(Candidate {
+                        guard_pos,
+                        direction: state.direction,
+                        phase: state.phase,
+                        time: state.time - 1,
+                        obstacle: state.position,
+                    });
+                }
+            }
+
+            guard_pos = state.position;
+        }
+
+        candidates
+    }
+
+    /// Simulates the guard from right before the candidate, with an obstacle added on it, returning whether it walks in a loop
+    fn loops_with_obstacle(
+        jumps: &JumpTable,
+        visits: &mut VisitedStates,
+        candidate: &Candidate,
+    ) -> bool {
+        let (mut guard_pos, mut direction) = (candidate.guard_pos, candidate.direction);
+
+        // Only the states at turns are tracked, as a loop must repeat those as well
+        while visits.insert(guard_pos, direction as usize) {
+            let jump = jumps.jump(guard_pos, direction);
+
+            guard_pos = match Self::blocked_by(guard_pos, direction, jump, candidate.obstacle) {
+                Some(stop) => stop,
+                None => match jump {
+                    Jump::Stop(stop) => stop,
+                    Jump::Exit => return false,
+                },
+            };
+
+            direction = direction.turn_right();
+        }
+
+        true
+    }
+
+    /// Same as [`Lab::loops_with_obstacle`], but walking one step at a time, following any rules
+    fn walk_loops_with_obstacle(&self, candidate: &Candidate) -> bool {
+        GuardWalk::from_state(
+            self,
+            candidate.guard_pos,
+            candidate.direction,
+            candidate.phase,
+            candidate.time,
+        )
+        .with_obstacle(candidate.obstacle)
+        .last()
+        .is_some_and(|state| state.event == Event::Looped)
+    }
+
+    /// Where the guard stops if the added obstacle is in its way before the jump ends
+    fn blocked_by(
+        from: Coordinate,
+        direction: Direction,
+        jump: Jump,
+        obstacle: Coordinate,
+    ) -> Option<Coordinate> {
+        let in_way = match (direction, jump) {
+            (Direction::Up, _) | (Direction::Down, _) if obstacle.1 != from.1 => false,
+            (Direction::Left, _) | (Direction::Right, _) if obstacle.0 != from.0 => false,
+            (Direction::Up, Jump::Stop(stop)) => obstacle.0 < from.0 && obstacle.0 >= stop.0,
+            (Direction::Up, Jump::Exit) => obstacle.0 < from.0,
+            (Direction::Down, Jump::Stop(stop)) => obstacle.0 > from.0 && obstacle.0 <= stop.0,
+            (Direction::Down, Jump::Exit) => obstacle.0 > from.0,
+            (Direction::Left, Jump::Stop(stop)) => obstacle.1 < from.1 && obstacle.1 >= stop.1,
+            (Direction::Left, Jump::Exit) => obstacle.1 < from.1,
+            (Direction::Right, Jump::Stop(stop)) => obstacle.1 > from.1 && obstacle.1 <= stop.1,
+            (Direction::Right, Jump::Exit) => obstacle.1 > from.1,
+            _ => unreachable!("Jumps only exist for cardinal directions"),
+        };
+
+        if !in_way {
+            return None;
+        }
+
+        let (x, y) = direction.offset();
+        obstacle.transform(-x, -y)
+    }
+}
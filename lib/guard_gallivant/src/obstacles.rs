@@ -0,0 +1,126 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use crate::{Coordinate, Event, GuardWalk, Lab, Tile};
+
+impl Lab {
+    /// The smallest set of added obstacles that keeps the guard in the lab forever, trying at most `max_obstacles`.
+    ///
+    /// Sets are searched by iterative deepening, where only cells on the guard's current route are tried,
+    /// as an obstacle anywhere else doesn't change where it walks.
+    /// Returns `None` if no set of up to `max_obstacles` obstacles traps the guard.
+    pub fn min_trapping_obstacles(&self, max_obstacles: usize) -> Option<Vec<Coordinate>> {
+        (0..=max_obstacles).find_map(|depth| {
+            let mut tried = HashSet::new();
+            self.trap(&mut Vec::new(), depth, &mut tried)
+        })
+    }
+
+    /// The added obstacle that makes the guard visit the most distinct cells before leaving the lab,
+    /// along with that amount of cells. Ties are broken by the smallest position.
+    ///
+    /// Returns `None` if there's nowhere to place an obstacle without trapping the guard.
+    pub fn max_visits_obstacle(&self) -> Option<(Coordinate, usize)> {
+        let route = self.route_cells(self.walk())?;
+
+        // An obstacle off the route doesn't change it, so only the first free cell off the route needs checking
+        let off_route = (0..self.map.height)
+            .flat_map(|row| (0..self.map.width).map(move |col| Coordinate(row, col)))
+            .find(|&cell| self.map.tile(cell) == Tile::Ground && !route.contains(&cell))
+            .map(|cell| (cell, route.len()));
+
+        let on_route = self.loop_candidates().into_iter().filter_map(|candidate| {
+            let route = self.route_cells(self.walk().with_obstacle(candidate.obstacle))?;
+            Some((candidate.obstacle, route.len()))
+        });
+
+        off_route
+            .into_iter()
+            .chain(on_route)
+            .max_by_key(|&(cell, visits)| (visits, Reverse(cell)))
+    }
+
+    /// The sorted positions of the obstacles the guard never bumps into,
+    /// which could all be removed without changing its route
+    pub fn removable_obstacles(&self) -> Vec<Coordinate> {
+        let mut bumped = HashSet::new();
+        let mut direction = self.start_direction;
+
+        for state in self.walk() {
+            if state.event == Event::Turned {
+                bumped.extend(self.map.step(state.position, direction));
+            }
+
+            direction = state.direction;
+        }
+
+        (0..self.map.height)
+            .flat_map(|row| (0..self.map.width).map(move |col| Coordinate(row, col)))
+            .filter(|&cell| self.map.tile(cell) == Tile::Obstacle && !bumped.contains(&cell))
+            .collect()
+    }
+
+    /// Searches for `remaining` more obstacles to add to the current ones, so the guard walks in a loop
+    fn trap(
+        &self,
+        obstacles: &mut Vec<Coordinate>,
+        remaining: usize,
+        tried: &mut HashSet<Vec<Coordinate>>,
+    ) -> Option<Vec<Coordinate>> {
+        let mut sorted = obstacles.clone();
+        sorted.sort_unstable();
+
+        // The same set can be reached by adding its obstacles in any order
+        if !tried.insert(sorted.clone()) {
+            return None;
+        }
+
+        let mut route = Vec::new();
+        let mut seen = HashSet::from([self.start_at]);
+
+        for state in self.walk().with_obstacles(obstacles.iter().copied()) {
+            match state.event {
+                Event::Looped => return Some(sorted),
+                Event::Moved if seen.insert(state.position) => route.push(state.position),
+                _ => {}
+            }
+        }
+
+        if remaining == 0 {
+            return None;
+        }
+
+        for cell in route {
+            if self.map.tile(cell) != Tile::Ground {
+                continue;
+            }
+
+            obstacles.push(cell);
+
+            if let Some(trap) = self.trap(obstacles, remaining - 1, tried) {
+                return Some(trap);
+            }
+
+            obstacles.pop();
+        }
+
+        None
+    }
+
+    /// Every cell the guard stands on during the walk, or `None` if it walks in a loop
+    fn route_cells(&self, walk: GuardWalk) -> Option<HashSet<Coordinate>> {
+        let mut cells = HashSet::from([self.start_at]);
+
+        for state in walk {
+            match state.event {
+                Event::Looped => return None,
+                Event::Moved | Event::Teleported => {
+                    cells.insert(state.position);
+                }
+                Event::Turned | Event::Exited => {}
+            }
+        }
+
+        Some(cells)
+    }
+}
@@ -0,0 +1,235 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Write};
+
+use crate::{Coordinate, Direction, Event, GuardWalk, Lab, State, Tile};
+
+const VERTICAL: u8 = 1;
+const HORIZONTAL: u8 = 2;
+const RISING: u8 = 4;
+const FALLING: u8 = 8;
+const TURN: u8 = 16;
+
+/// Symbols of one-way tiles, by the direction they can be entered in
+const ONE_WAY: [char; 8] = ['⇑', '⇗', '⇒', '⇘', '⇓', '⇙', '⇐', '⇖'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Cell {
+    Ground,
+    Obstacle,
+    /// An obstacle that isn't part of the original lab
+    Added,
+    /// Ground the guard walked over, as a set of the `VERTICAL`, `HORIZONTAL`, `RISING`, `FALLING` and `TURN` flags
+    Path(u8),
+    /// Any other tile, which the path isn't drawn over
+    Special(Tile),
+}
+
+impl Cell {
+    fn symbol(&self) -> char {
+        match *self {
+            Cell::Ground => '.',
+            Cell::Obstacle => '#',
+            Cell::Added => 'O',
+            Cell::Path(VERTICAL) => '|',
+            Cell::Path(HORIZONTAL) => '-',
+            Cell::Path(RISING) => '/',
+            Cell::Path(FALLING) => '\\',
+            Cell::Path(_) => '+',
+            Cell::Special(Tile::OneWay(direction)) => ONE_WAY[direction as usize],
+            Cell::Special(Tile::Teleporter(_)) => '@',
+            Cell::Special(_) => '%',
+        }
+    }
+
+    fn color(&self) -> [u8; 3] {
+        match self {
+            Cell::Ground => [24, 24, 32],
+            Cell::Obstacle => [160, 160, 160],
+            Cell::Added => [220, 40, 40],
+            Cell::Path(_) => [40, 120, 220],
+            Cell::Special(Tile::Teleporter(_)) => [160, 60, 200],
+            Cell::Special(_) => [100, 100, 120],
+        }
+    }
+}
+
+/// The lab with the guard's route drawn onto it, like in the puzzle description.
+///
+/// Displays as text, and can be written as a PPM image.
+#[derive(Debug, Clone)]
+pub struct Rendering {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+    start: (Coordinate, Direction),
+    guard: Option<(Coordinate, Direction)>,
+}
+
+impl Rendering {
+    fn new(lab: &Lab, obstacles: &[Coordinate]) -> Self {
+        let (height, width) = (lab.map.height, lab.map.width);
+        let mut cells: Vec<Cell> = (0..height)
+            .flat_map(|row| (0..width).map(move |col| (row, col)))
+            .map(|(row, col)| match lab.map.tile(Coordinate(row, col)) {
+                Tile::Ground => Cell::Ground,
+                Tile::Obstacle => Cell::Obstacle,
+                tile => Cell::Special(tile),
+            })
+            .collect();
+
+        for obstacle in obstacles.iter().filter(|o| o.0 < height && o.1 < width) {
+            cells[obstacle.0 * width + obstacle.1] = Cell::Added;
+        }
+
+        Self {
+            width,
+            height,
+            cells,
+            start: (lab.start_at, lab.start_direction),
+            guard: None,
+        }
+    }
+
+    fn mark(&mut self, coordinate: Coordinate, flag: u8) {
+        let cell = &mut self.cells[coordinate.0 * self.width + coordinate.1];
+
+        *cell = match *cell {
+            Cell::Path(flags) => Cell::Path(flags | flag),
+            Cell::Special(tile) => Cell::Special(tile),
+            _ => Cell::Path(flag),
+        };
+    }
+
+    /// Draws a single step of the guard onto the route
+    fn apply(&mut self, from: Coordinate, state: &State) {
+        let axis = match state.direction {
+            Direction::Up | Direction::Down => VERTICAL,
+            Direction::Left | Direction::Right => HORIZONTAL,
+            Direction::UpRight | Direction::DownLeft => RISING,
+            Direction::DownRight | Direction::UpLeft => FALLING,
+        };
+
+        match state.event {
+            Event::Moved => {
+                self.mark(from, axis);
+                self.mark(state.position, axis);
+            }
+            Event::Teleported => self.mark(from, axis),
+            Event::Turned => self.mark(state.position, TURN),
+            Event::Exited | Event::Looped => self.mark(state.position, axis),
+        }
+
+        self.guard = Some((state.position, state.direction));
+    }
+
+    fn symbol_at(&self, coordinate: Coordinate) -> char {
+        match self.guard {
+            Some((guard, direction)) if guard == coordinate => direction.symbol(),
+            None if coordinate == self.start.0 => self.start.1.symbol(),
+            _ => self.cells[coordinate.0 * self.width + coordinate.1].symbol(),
+        }
+    }
+
+    /// Writes the rendering as a binary PPM image, with every cell drawn as a `scale` x `scale` square
+    pub fn write_ppm(&self, mut writer: impl Write, scale: usize) -> io::Result<()> {
+        let scale = scale.max(1);
+        write!(
+            writer,
+            "P6\n{} {}\n255\n",
+            self.width * scale,
+            self.height * scale
+        )?;
+
+        for row in 0..self.height {
+            let line: Vec<u8> = (0..self.width)
+                .flat_map(|col| {
+                    let coordinate = Coordinate(row, col);
+                    let color = match self.guard {
+                        Some((guard, _)) if guard == coordinate => [240, 200, 40],
+                        None if coordinate == self.start.0 => [40, 200, 80],
+                        _ => self.cells[row * self.width + col].color(),
+                    };
+
+                    std::iter::repeat_n(color, scale).flatten()
+                })
+                .collect();
+
+            for _ in 0..scale {
+                writer.write_all(&line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for Rendering {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for row in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|col| self.symbol_at(Coordinate(row, col)))
+                .collect();
+
+            writeln!(f, "{line}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Iterator over the guard's patrol as animation frames, see [`Lab::frames`]
+pub struct Frames<'a> {
+    walk: GuardWalk<'a>,
+    rendering: Rendering,
+    position: Coordinate,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Rendering;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let state = self.walk.next()?;
+
+        self.rendering.apply(self.position, &state);
+        self.position = state.position;
+
+        Some(self.rendering.clone())
+    }
+}
+
+impl Lab {
+    /// Renders the guard's whole route, with the given obstacles added to the lab and drawn as `O`
+    pub fn render(&self, obstacles: &[Coordinate]) -> Rendering {
+        let mut rendering = Rendering::new(self, obstacles);
+        let mut position = self.start_at;
+
+        for state in self.walk().with_obstacles(obstacles.iter().copied()) {
+            rendering.apply(position, &state);
+            position = state.position;
+        }
+
+        rendering.guard = None;
+        rendering
+    }
+
+    /// Renders the guard's original route, with every position where an added obstacle would
+    /// make the guard walk in a loop drawn as `O`
+    pub fn render_looping_obstacles(&self) -> Rendering {
+        let mut rendering = self.render(&[]);
+
+        for obstacle in self.looping_obstacles() {
+            rendering.cells[obstacle.0 * rendering.width + obstacle.1] = Cell::Added;
+        }
+
+        rendering
+    }
+
+    /// Renders every step of the guard's route as a frame, with the guard drawn at its current position
+    pub fn frames(&self, obstacles: &[Coordinate]) -> Frames<'_> {
+        Frames {
+            walk: self.walk().with_obstacles(obstacles.iter().copied()),
+            rendering: Rendering::new(self, obstacles),
+            position: self.start_at,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use crate::Direction;
+
+/// Which way the guard turns when facing an obstacle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Turn {
+    Right,
+    Left,
+    Reverse,
+}
+
+/// Which directions the guard turns between
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Movement {
+    /// Turns are 90 degrees, like in the puzzle
+    #[default]
+    Four,
+    /// Turns are 45 degrees, so the guard also walks diagonally
+    Eight,
+}
+
+/// How the guard patrols the lab.
+///
+/// Defaults to the puzzle's rules: turn right on every obstacle, moving in four directions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rules {
+    turns: Vec<Turn>,
+    movement: Movement,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            turns: vec![Turn::Right],
+            movement: Movement::default(),
+        }
+    }
+}
+
+impl Rules {
+    /// Makes the guard turn the same way on every obstacle
+    pub fn with_turn(mut self, turn: Turn) -> Self {
+        self.turns = vec![turn];
+        self
+    }
+
+    /// Makes the guard cycle through the turns, taking the next one every time it faces an obstacle.
+    /// An empty sequence keeps the current turns.
+    pub fn with_turn_sequence(mut self, turns: impl IntoIterator<Item = Turn>) -> Self {
+        let turns: Vec<Turn> = turns.into_iter().collect();
+
+        if !turns.is_empty() {
+            self.turns = turns;
+        }
+
+        self
+    }
+
+    pub fn with_movement(mut self, movement: Movement) -> Self {
+        self.movement = movement;
+        self
+    }
+
+    /// The direction after facing an obstacle at the given position of the turn sequence, along with the next position
+    pub(crate) fn turn(&self, direction: Direction, phase: usize) -> (Direction, usize) {
+        let step = match self.movement {
+            Movement::Four => 2,
+            Movement::Eight => 1,
+        };
+        let eighths = match self.turns[phase] {
+            Turn::Right => step,
+            Turn::Left => Direction::ALL.len() - step,
+            Turn::Reverse => Direction::ALL.len() / 2,
+        };
+
+        (direction.rotate(eighths), (phase + 1) % self.turns.len())
+    }
+}
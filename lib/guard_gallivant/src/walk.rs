@@ -0,0 +1,192 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{lcm, Coordinate, Direction, Lab, Tile};
+
+/// What happened to the guard in a single step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The guard stepped forward onto a new cell
+    Moved,
+    /// The guard stepped onto a teleporter, and came out on its partner
+    Teleported,
+    /// The guard faced an obstacle and turned
+    Turned,
+    /// The guard stepped out of the lab, from the given state
+    Exited,
+    /// The guard is back in a state it has already been in, so it will walk in a loop forever
+    Looped,
+}
+
+/// The guard's state after a single step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct State {
+    pub position: Coordinate,
+    pub direction: Direction,
+    /// The position in the rules' turn sequence, for the next obstacle the guard faces
+    pub phase: usize,
+    /// The amount of steps taken
+    pub time: usize,
+    pub event: Event,
+}
+
+/// Iterator simulating the guard's patrol one step at a time.
+///
+/// Ends after yielding an [`Event::Exited`] or [`Event::Looped`] state.
+pub struct GuardWalk<'a> {
+    lab: &'a Lab,
+    obstacles: HashSet<Coordinate>,
+    position: Coordinate,
+    direction: Direction,
+    phase: usize,
+    time: usize,
+    /// The amount of steps after which every periodic obstacle the guard ran into is back in the same state
+    period: usize,
+    /// The time of every visited state, keyed by everything that decides where the guard goes next
+    visited: HashMap<(Coordinate, Direction, usize, usize), usize>,
+    /// The time of the state the guard looped back to
+    loop_start: Option<usize>,
+    done: bool,
+}
+
+impl<'a> GuardWalk<'a> {
+    pub(crate) fn new(lab: &'a Lab) -> Self {
+        Self::from_state(lab, lab.start_at, lab.start_direction, 0, 0)
+    }
+
+    /// Continues a walk from the given state
+    pub(crate) fn from_state(
+        lab: &'a Lab,
+        position: Coordinate,
+        direction: Direction,
+        phase: usize,
+        time: usize,
+    ) -> Self {
+        Self {
+            lab,
+            obstacles: HashSet::new(),
+            position,
+            direction,
+            phase,
+            time,
+            period: 1,
+            visited: HashMap::new(),
+            loop_start: None,
+            done: false,
+        }
+    }
+
+    /// Adds an extra obstacle to the lab for this walk
+    pub fn with_obstacle(mut self, obstacle: Coordinate) -> Self {
+        self.obstacles.insert(obstacle);
+        self
+    }
+
+    /// Adds several extra obstacles to the lab for this walk
+    pub fn with_obstacles(mut self, obstacles: impl IntoIterator<Item = Coordinate>) -> Self {
+        self.obstacles.extend(obstacles);
+        self
+    }
+
+    /// Forgets the visited states, so a walk that looped carries on walking the loop
+    pub(crate) fn restart(&mut self) {
+        self.visited.clear();
+        self.loop_start = None;
+        self.done = false;
+    }
+
+    /// Walks until the guard leaves the lab or loops, returning its position and direction before the
+    /// first step and after every step, along with the index of the first state of the loop if it walks in one
+    pub(crate) fn trace(mut self) -> (Vec<(Coordinate, Direction)>, Option<usize>) {
+        let start = self.time;
+        let mut states = vec![(self.position, self.direction)];
+
+        for state in self.by_ref() {
+            if matches!(
+                state.event,
+                Event::Moved | Event::Teleported | Event::Turned
+            ) {
+                states.push((state.position, state.direction));
+            }
+        }
+
+        // The state the guard looped back to was pushed twice, so the loop runs up to the last one
+        if self.loop_start.is_some() {
+            states.pop();
+        }
+
+        (states, self.loop_start.map(|time| time - start))
+    }
+
+    fn state(&self, event: Event) -> State {
+        State {
+            position: self.position,
+            direction: self.direction,
+            phase: self.phase,
+            time: self.time,
+            event,
+        }
+    }
+}
+
+impl Iterator for GuardWalk<'_> {
+    type Item = State;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let key = (
+            self.position,
+            self.direction,
+            self.phase,
+            self.time % self.period,
+        );
+
+        if let Some(&time) = self.visited.get(&key) {
+            self.done = true;
+            self.loop_start = Some(time);
+            return Some(self.state(Event::Looped));
+        }
+
+        self.visited.insert(key, self.time);
+
+        let next = self.lab.map.step(self.position, self.direction);
+        let blocked = next.is_some_and(|next| {
+            self.obstacles.contains(&next) || self.lab.map.blocks(next, self.direction, self.time)
+        });
+
+        // Only the periodic obstacles the guard runs into decide where it goes, so states are only compared
+        // over the period of those. When it runs into a new one, earlier states can't be compared anymore.
+        if let Some(Tile::Periodic { period, .. }) = next.map(|next| self.lab.map.tile(next)) {
+            // Can't overflow, as the map checked the period of all its periodic obstacles together
+            let period = Tile::cycle(period).and_then(|cycle| lcm(self.period, cycle));
+            let period = period.expect("period of the map's periodic obstacles overflows");
+
+            if period != self.period {
+                self.period = period;
+                self.visited.clear();
+            }
+        }
+
+        self.time += 1;
+
+        let Some(next) = next else {
+            self.done = true;
+            return Some(self.state(Event::Exited));
+        };
+
+        if blocked {
+            (self.direction, self.phase) = self.lab.rules.turn(self.direction, self.phase);
+            return Some(self.state(Event::Turned));
+        }
+
+        if let Some(&destination) = self.lab.map.teleports.get(&next) {
+            self.position = destination;
+            return Some(self.state(Event::Teleported));
+        }
+
+        self.position = next;
+        Some(self.state(Event::Moved))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::lexer::{Scanner, Signature, Syntax};
+use crate::OverflowError;
+
+/// State of the machine running the instructions found in corrupted memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    /// Whether instructions depending on being enabled should have an effect
+    pub enabled: bool,
+    pub value: usize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            value: 0,
+        }
+    }
+}
+
+/// What an instruction does to the state, given its arguments.
+/// Returning `None` means the calculation overflowed.
+pub type Semantics = Box<dyn Fn(&mut State, &[usize]) -> Option<()>>;
+
+/// Runs corrupted memory with a configurable set of instructions
+#[derive(Default)]
+pub struct Interpreter {
+    signatures: Vec<Signature>,
+    semantics: Vec<Semantics>,
+    syntax: Syntax,
+}
+
+impl Interpreter {
+    /// An interpreter without any instructions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An interpreter with the instructions of the original puzzle, `mul(a,b)`, `do()` and `don't()`
+    pub fn standard() -> Self {
+        Self::new()
+            .register("mul", 2, |state, args| {
+                if state.enabled {
+                    state.value = state.value.checked_add(args[0].checked_mul(args[1])?)?;
+                }
+                Some(())
+            })
+            .register("do", 0, |state, _| {
+                state.enabled = true;
+                Some(())
+            })
+            .register("don't", 0, |state, _| {
+                state.enabled = false;
+                Some(())
+            })
+    }
+
+    /// Adds an instruction, written as `name(arg,...)` with `arity` arguments.
+    /// Registering an existing name replaces that instruction.
+    pub fn register(
+        mut self,
+        name: &str,
+        arity: usize,
+        semantics: impl Fn(&mut State, &[usize]) -> Option<()> + 'static,
+    ) -> Self {
+        let signature = Signature {
+            name: name.to_string().into(),
+            arity,
+            literal: false,
+        };
+
+        match self.signatures.iter().position(|s| s.name == name) {
+            Some(opcode) => {
+                self.signatures[opcode] = signature;
+                self.semantics[opcode] = Box::new(semantics);
+            }
+            None => {
+                self.signatures.push(signature);
+                self.semantics.push(Box::new(semantics));
+            }
+        }
+
+        self
+    }
+
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Runs every instruction found in the input, in order
+    pub fn run(&self, input: &str) -> Result<State, OverflowError> {
+        let mut state = State::default();
+
+        for call in Scanner::new(input, &self.signatures, self.syntax) {
+            self.semantics[call.opcode](&mut state, &call.args)
+                .ok_or(OverflowError { span: call.span })?;
+        }
+
+        Ok(state)
+    }
+}
@@ -0,0 +1,448 @@
+use std::borrow::Cow;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// An instruction recognized in corrupted memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Mul(usize, usize),
+    /// Enables multiplications, written as `do()` unless configured otherwise
+    Do,
+    /// Disables multiplications, written as `don't()` unless configured otherwise
+    Dont,
+}
+
+/// Displays toggles with their default keywords, whichever keywords they were read as
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mul(a, b) => write!(f, "mul({a},{b})"),
+            Self::Do => write!(f, "do()"),
+            Self::Dont => write!(f, "don't()"),
+        }
+    }
+}
+
+/// An instruction, along with the bytes of the input it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub instruction: Instruction,
+    pub span: Range<usize>,
+}
+
+/// Which arguments are accepted for an instruction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Exactly the expected amount of 1-3 digit arguments, separated by commas without any whitespace
+    #[default]
+    Strict,
+    /// Anything up until the closing parenthesis, as long as the first comma-separated arguments are numbers.
+    ///
+    /// Calls inside the arguments of another call are read as well, so calls may overlap.
+    Lenient,
+}
+
+/// Most digits an argument can have with [`Syntax::Strict`]
+const MAX_STRICT_DIGITS: usize = 3;
+
+/// How the enable and disable keywords affect each other
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ToggleMode {
+    /// The last keyword decides whether multiplications are enabled
+    #[default]
+    Simple,
+    /// Every disable keyword must be undone by its own enable keyword
+    Stacked,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserConfig {
+    pub syntax: Syntax,
+    /// Keyword enabling multiplications, matched exactly as written
+    pub enable: Cow<'static, str>,
+    /// Keyword disabling multiplications, matched exactly as written
+    pub disable: Cow<'static, str>,
+    pub toggles: ToggleMode,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            syntax: Syntax::default(),
+            enable: Cow::Borrowed("do()"),
+            disable: Cow::Borrowed("don't()"),
+            toggles: ToggleMode::default(),
+        }
+    }
+}
+
+/// The name and amount of arguments of an instruction, which is written as `name(arg,...)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub name: Cow<'static, str>,
+    pub arity: usize,
+    /// Whether the name is a keyword written exactly as is, without parentheses or arguments
+    pub literal: bool,
+}
+
+impl Signature {
+    pub const fn new(name: &'static str, arity: usize) -> Self {
+        Self {
+            name: Cow::Borrowed(name),
+            arity,
+            literal: false,
+        }
+    }
+
+    pub fn keyword(keyword: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: keyword.into(),
+            arity: 0,
+            literal: true,
+        }
+    }
+}
+
+const MUL: usize = 0;
+const ENABLE: usize = 1;
+const DISABLE: usize = 2;
+
+/// Tracks whether multiplications are enabled, as the enable and disable instructions are run
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Toggles {
+    mode: ToggleMode,
+    /// Amount of disable instructions that haven't been undone yet
+    disabled: usize,
+}
+
+impl Toggles {
+    pub(crate) const fn new(mode: ToggleMode) -> Self {
+        Self { mode, disabled: 0 }
+    }
+
+    pub(crate) const fn enabled(&self) -> bool {
+        self.disabled == 0
+    }
+
+    pub(crate) fn apply(&mut self, instruction: Instruction) {
+        self.disabled = match (instruction, self.mode) {
+            (Instruction::Mul(..), _) => self.disabled,
+            (Instruction::Do, ToggleMode::Simple) => 0,
+            (Instruction::Dont, ToggleMode::Simple) => 1,
+            (Instruction::Do, ToggleMode::Stacked) => self.disabled.saturating_sub(1),
+            (Instruction::Dont, ToggleMode::Stacked) => self.disabled + 1,
+        };
+    }
+}
+
+/// A call to one of the instructions given to a [`Scanner`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    /// Index of the instruction's signature
+    pub opcode: usize,
+    pub args: Vec<usize>,
+    pub span: Range<usize>,
+}
+
+/// Outcome of trying to read something at a position in the input
+enum Match<T> {
+    Found(T),
+    /// The input ends before it's known whether there's a match, so more input is needed
+    Incomplete,
+    NotFound,
+}
+
+/// Single-pass scanner, finding every call to the given instructions in corrupted memory and skipping everything else
+pub struct Scanner<'a> {
+    input: &'a [u8],
+    position: usize,
+    signatures: Cow<'a, [Signature]>,
+    syntax: Syntax,
+    /// Most bytes a call can span with [`Syntax::Lenient`], from its name up to the closing parenthesis
+    max_call_length: usize,
+    /// Whether the input is all there is, or if more may follow when streaming
+    complete: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(input: &'a str, signatures: &'a [Signature], syntax: Syntax) -> Self {
+        Self {
+            input: input.as_bytes(),
+            position: 0,
+            signatures: Cow::Borrowed(signatures),
+            syntax,
+            max_call_length: usize::MAX,
+            complete: true,
+        }
+    }
+
+    /// Sets the most bytes a call can span with [`Syntax::Lenient`], from its name up to the closing parenthesis.
+    /// Longer calls aren't read. Calls can be as long as the input by default.
+    pub fn with_max_call_length(mut self, max_call_length: usize) -> Self {
+        self.max_call_length = max_call_length;
+        self
+    }
+
+    /// Scans the buffered part of a stream.
+    /// Unless the stream has ended, this stops before any call that may continue in the next chunk.
+    pub(crate) fn from_stream(
+        input: &'a [u8],
+        signatures: Cow<'a, [Signature]>,
+        syntax: Syntax,
+        end_of_stream: bool,
+    ) -> Self {
+        Self {
+            input,
+            position: 0,
+            signatures,
+            syntax,
+            max_call_length: usize::MAX,
+            complete: end_of_stream,
+        }
+    }
+
+    /// How far into the input the scanner has gotten
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Tries to read a call at the start of `rest`, returning its opcode, arguments and length in bytes.
+    ///
+    /// When several calls match, such as keywords where one starts with the other, the longest one is read.
+    fn scan_at(&self, rest: &[u8]) -> Match<(usize, Vec<usize>, usize)> {
+        let mut longest: Option<(usize, Vec<usize>, usize)> = None;
+
+        for (opcode, signature) in self.signatures.iter().enumerate() {
+            match self.scan_signature(signature, rest) {
+                Match::Found((args, len)) => {
+                    if longest.as_ref().is_none_or(|longest| len > longest.2) {
+                        longest = Some((opcode, args, len));
+                    }
+                }
+                // A longer call might continue in the next chunk
+                Match::Incomplete if !self.complete => return Match::Incomplete,
+                Match::Incomplete | Match::NotFound => {}
+            }
+        }
+
+        longest.map_or(Match::NotFound, Match::Found)
+    }
+
+    /// Tries to read a call to a single instruction at the start of `rest`, returning its arguments and length in bytes
+    fn scan_signature(&self, signature: &Signature, rest: &[u8]) -> Match<(Vec<usize>, usize)> {
+        let name = signature.name.as_bytes();
+
+        if signature.literal {
+            return match name {
+                // An empty keyword would match everywhere without ever moving on
+                [] => Match::NotFound,
+                _ if rest.starts_with(name) => Match::Found((Vec::new(), name.len())),
+                _ if rest.len() < name.len() && name.starts_with(rest) => Match::Incomplete,
+                _ => Match::NotFound,
+            };
+        }
+
+        if rest.len() <= name.len() {
+            // The name might continue in the next chunk
+            if name.starts_with(rest) {
+                return Match::Incomplete;
+            }
+
+            return Match::NotFound;
+        }
+
+        if !rest.starts_with(name) || rest[name.len()] != b'(' {
+            return Match::NotFound;
+        }
+
+        let start = name.len() + 1;
+        match (signature.arity, self.syntax) {
+            (0, _) => match rest.get(start) {
+                Some(b')') => Match::Found((Vec::new(), start + 1)),
+                Some(_) => Match::NotFound,
+                None => Match::Incomplete,
+            },
+            (arity, Syntax::Strict) => Self::scan_args_strict(rest, start, arity),
+            (arity, Syntax::Lenient) => {
+                Self::scan_args_lenient(rest, start, arity, self.max_call_length)
+            }
+        }
+    }
+
+    /// Reads exactly `arity` short numbers separated by commas, starting at `start` and ending with a closing parenthesis
+    fn scan_args_strict(rest: &[u8], start: usize, arity: usize) -> Match<(Vec<usize>, usize)> {
+        let mut args = Vec::with_capacity(arity);
+        let mut position = start;
+
+        for idx in 0..arity {
+            let digits = rest[position..]
+                .iter()
+                .take(MAX_STRICT_DIGITS + 1)
+                .take_while(|b| b.is_ascii_digit())
+                .count();
+
+            if digits > MAX_STRICT_DIGITS {
+                return Match::NotFound;
+            }
+
+            let separator = if idx + 1 == arity { b')' } else { b',' };
+
+            match rest.get(position + digits) {
+                None => return Match::Incomplete,
+                Some(&b) if digits == 0 || b != separator => return Match::NotFound,
+                Some(_) => {}
+            }
+
+            args.push(
+                rest[position..position + digits]
+                    .iter()
+                    .fold(0, |num, digit| num * 10 + usize::from(digit - b'0')),
+            );
+            position += digits + 1;
+        }
+
+        Match::Found((args, position))
+    }
+
+    /// Reads everything up until the next closing parenthesis, parsing the first `arity` comma-separated arguments.
+    /// The whole call has to fit in `max_len` bytes.
+    fn scan_args_lenient(
+        rest: &[u8],
+        start: usize,
+        arity: usize,
+        max_len: usize,
+    ) -> Match<(Vec<usize>, usize)> {
+        let limit = rest.len().min(max_len);
+        let end = rest
+            .get(start..limit)
+            .and_then(|args| args.iter().position(|&b| b == b')'));
+
+        let Some(end) = end else {
+            // The call might still be closed in the next chunk, unless it's already too long
+            return match rest.len() < max_len {
+                true => Match::Incomplete,
+                false => Match::NotFound,
+            };
+        };
+        let end = end + start;
+
+        let args = std::str::from_utf8(&rest[start..end])
+            .ok()
+            .and_then(|args| {
+                args.split(',')
+                    .take(arity)
+                    .map(|arg| arg.parse().ok())
+                    .collect::<Option<Vec<usize>>>()
+            });
+
+        match args {
+            Some(args) if args.len() == arity => Match::Found((args, end + 1)),
+            _ => Match::NotFound,
+        }
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Call;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.position < self.input.len() {
+            let start = self.position;
+
+            let (opcode, args, len) = match self.scan_at(&self.input[start..]) {
+                Match::Found(call) => call,
+                // Wait for the rest of the call, which the stream can provide later
+                Match::Incomplete if !self.complete => return None,
+                Match::Incomplete | Match::NotFound => {
+                    self.position += 1;
+                    continue;
+                }
+            };
+
+            let signature = &self.signatures[opcode];
+            self.position += match self.syntax {
+                // Lenient arguments may contain other calls, which are still read on their own
+                Syntax::Lenient if !signature.literal => signature.name.len() + 1,
+                _ => len,
+            };
+
+            return Some(Call {
+                opcode,
+                args,
+                span: start..start + len,
+            });
+        }
+
+        None
+    }
+}
+
+/// Single-pass lexer, finding every instruction of the original puzzle in corrupted memory
+pub struct Lexer<'a> {
+    scanner: Scanner<'a>,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self::from_stream(input.as_bytes(), true)
+    }
+
+    /// Lexes the buffered part of a stream, see [`Scanner::from_stream`]
+    pub(crate) fn from_stream(input: &'a [u8], end_of_stream: bool) -> Self {
+        let config = ParserConfig::default();
+
+        Self {
+            scanner: Scanner::from_stream(
+                input,
+                Self::signatures(&config).into(),
+                config.syntax,
+                end_of_stream,
+            ),
+        }
+    }
+
+    pub fn with_config(mut self, config: ParserConfig) -> Self {
+        self.scanner.signatures = Self::signatures(&config).into();
+        self.scanner.syntax = config.syntax;
+        self
+    }
+
+    /// Sets the most bytes a call can span with [`Syntax::Lenient`], see [`Scanner::with_max_call_length`]
+    pub(crate) fn with_max_call_length(mut self, max_call_length: usize) -> Self {
+        self.scanner.max_call_length = max_call_length;
+        self
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.scanner.position()
+    }
+
+    /// The instructions to look for, indexed by their opcodes
+    fn signatures(config: &ParserConfig) -> Vec<Signature> {
+        vec![
+            Signature::new("mul", 2),
+            Signature::keyword(config.enable.clone()),
+            Signature::keyword(config.disable.clone()),
+        ]
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let call = self.scanner.next()?;
+
+        let instruction = match call.opcode {
+            MUL => Instruction::Mul(call.args[0], call.args[1]),
+            ENABLE => Instruction::Do,
+            DISABLE => Instruction::Dont,
+            _ => unreachable!("Unknown opcode"),
+        };
+
+        Some(Token {
+            instruction,
+            span: call.span,
+        })
+    }
+}
@@ -1,79 +1,259 @@
+mod interpreter;
+mod lexer;
+mod stream;
+
+pub use interpreter::{Interpreter, Semantics, State};
+pub use lexer::{
+    Call, Instruction, Lexer, ParserConfig, Scanner, Signature, Syntax, ToggleMode, Token,
+};
+pub use stream::{StreamError, StreamEvaluator, Sums};
+
+use lexer::Toggles;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
 use std::str::FromStr;
 
 /// Failed to parse multiplication
 pub struct ParseError;
 
+/// A calculation overflowed (or underflowed)
+#[derive(Debug, PartialEq, Eq)]
+pub struct OverflowError {
+    /// The bytes of the instruction that caused the overflow
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Multiplication {
     enabled: bool,
     a: usize,
     b: usize,
+    span: Range<usize>,
 }
 
 impl Multiplication {
-    pub const fn get_result(&self) -> usize {
-        self.a * self.b
+    /// Whether the multiplication was enabled by the toggles before it
+    pub const fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub const fn a(&self) -> usize {
+        self.a
+    }
+
+    pub const fn b(&self) -> usize {
+        self.b
+    }
+
+    /// The bytes of the input the multiplication was read from
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    pub fn get_result(&self) -> Result<usize, OverflowError> {
+        self.a.checked_mul(self.b).ok_or_else(|| self.overflow())
     }
 
-    pub const fn get_result_conditional(&self) -> Option<usize> {
+    pub fn get_result_conditional(&self) -> Option<Result<usize, OverflowError>> {
         if self.enabled {
             return Some(self.get_result());
         }
 
         None
     }
+
+    fn add_to(&self, sum: usize) -> Result<usize, OverflowError> {
+        sum.checked_add(self.get_result()?)
+            .ok_or_else(|| self.overflow())
+    }
+
+    fn overflow(&self) -> OverflowError {
+        OverflowError {
+            span: self.span.clone(),
+        }
+    }
 }
 
-pub struct Calculations(Vec<Multiplication>);
+/// Products of the multiplications, grouped by whether they were enabled
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct GroupedProducts {
+    pub enabled: Vec<usize>,
+    pub disabled: Vec<usize>,
+}
+
+pub struct Calculations {
+    /// The input the tokens were read from, which their spans point into
+    input: String,
+    tokens: Vec<Token>,
+    config: ParserConfig,
+}
 
 impl Calculations {
-    pub fn sum(&self) -> usize {
-        self.0.iter().map(|m| m.get_result()).sum()
+    pub fn parse(s: &str, config: ParserConfig) -> Self {
+        Self {
+            input: s.to_string(),
+            tokens: Lexer::new(s).with_config(config.clone()).collect(),
+            config,
+        }
     }
 
-    pub fn sum_conditional(&self) -> usize {
-        self.0
-            .iter()
-            .filter_map(|m| m.get_result_conditional())
-            .sum()
+    pub fn sum(&self) -> Result<usize, OverflowError> {
+        self.iter().try_fold(0, |sum, m| m.add_to(sum))
     }
-}
 
-impl FromStr for Calculations {
-    type Err = ();
+    pub fn sum_conditional(&self) -> Result<usize, OverflowError> {
+        self.iter()
+            .filter(|m| m.enabled)
+            .try_fold(0, |sum, m| m.add_to(sum))
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut do_matches = s.match_indices("do()").map(|(idx, _)| idx);
-        let mut do_not_matches = s.match_indices("don't()").map(|(idx, _)| idx);
-        let mul_matches = s.match_indices("mul(");
-
-        let mut next_do = do_matches.next().unwrap_or(s.len());
-        let mut next_do_not = do_not_matches.next().unwrap_or(s.len());
-
-        let mut enabled = true;
-
-        let calculations = mul_matches
-            .filter_map(|(idx, _)| {
-                if next_do > next_do_not && next_do_not <= idx {
-                    next_do_not = do_not_matches.next().unwrap_or(s.len());
-                    enabled = false;
-                } else if next_do_not > next_do && next_do <= idx {
-                    next_do = do_matches.next().unwrap_or(s.len());
-                    enabled = true;
+    pub fn count_enabled(&self) -> usize {
+        self.iter().filter(|m| m.enabled).count()
+    }
+
+    pub fn count_disabled(&self) -> usize {
+        self.iter().filter(|m| !m.enabled).count()
+    }
+
+    /// The largest product of any multiplication, enabled or not
+    pub fn max_product(&self) -> Result<Option<usize>, OverflowError> {
+        self.iter().try_fold(None, |max: Option<usize>, m| {
+            let product = m.get_result()?;
+            Ok(Some(max.map_or(product, |max| max.max(product))))
+        })
+    }
+
+    pub fn products_by_state(&self) -> Result<GroupedProducts, OverflowError> {
+        self.iter()
+            .try_fold(GroupedProducts::default(), |mut grouped, m| {
+                let product = m.get_result()?;
+
+                match m.enabled {
+                    true => grouped.enabled.push(product),
+                    false => grouped.disabled.push(product),
                 }
 
-                let start = idx + 4;
-                let end = s[start..].find(')')? + start;
+                Ok(grouped)
+            })
+    }
+
+    /// Lists every recognized instruction along with its result and the running conditional sum
+    pub fn listing(&self) -> Listing<'_> {
+        Listing(self)
+    }
+
+    /// Highlights the input these calculations were parsed from using ANSI colors,
+    /// showing enabled multiplications in green, disabled ones in red, toggles in cyan, and ignored bytes dimmed
+    pub fn highlight(&self) -> String {
+        let input = &self.input;
+        let mut highlighted = String::with_capacity(input.len() * 2);
+        let mut position = 0;
+
+        for (token, enabled) in self.annotated() {
+            let color = match token.instruction {
+                Instruction::Mul(..) if enabled => GREEN,
+                Instruction::Mul(..) => RED,
+                Instruction::Do | Instruction::Dont => CYAN,
+            };
 
-                // Parse multiplication
-                let mut split = s[start..end].split(',');
-                let a = split.next()?.parse().ok()?;
-                let b = split.next()?.parse().ok()?;
+            // Lenient calls may overlap, in which case only the part after the previous call is highlighted
+            let start = token.span.start.max(position);
+            let end = token.span.end.max(start);
+
+            highlighted.push_str(&format!(
+                "{DIM}{}{RESET}{color}{}{RESET}",
+                &input[position..start],
+                &input[start..end]
+            ));
+            position = end;
+        }
+
+        highlighted.push_str(&format!("{DIM}{}{RESET}", &input[position..]));
+        highlighted
+    }
 
-                Some(Multiplication { enabled, a, b })
+    /// Evaluates the instructions in order, yielding every multiplication along with whether it's enabled
+    pub fn iter(&self) -> impl Iterator<Item = Multiplication> + '_ {
+        self.annotated()
+            .filter_map(|(token, enabled)| match token.instruction {
+                Instruction::Mul(a, b) => Some(Multiplication {
+                    enabled,
+                    a,
+                    b,
+                    span: token.span.clone(),
+                }),
+                Instruction::Do | Instruction::Dont => None,
             })
-            .collect();
+    }
+
+    /// Every instruction, along with whether multiplications are enabled after it has run
+    fn annotated(&self) -> impl Iterator<Item = (&Token, bool)> + '_ {
+        let mut toggles = Toggles::new(self.config.toggles);
+
+        self.tokens.iter().map(move |token| {
+            toggles.apply(token.instruction);
+            (token, toggles.enabled())
+        })
+    }
+}
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const CYAN: &str = "\x1b[36m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Annotated listing of the instructions in [`Calculations`], see [`Calculations::listing`]
+pub struct Listing<'a>(&'a Calculations);
 
-        Ok(Self(calculations))
+impl Display for Listing<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:>8}  {:<16} {:<8} {:>12} {:>16}",
+            "Offset", "Instruction", "Enabled", "Value", "Conditional sum"
+        )?;
+
+        // The running sum is `None` once it has overflowed
+        let mut running = Some(0usize);
+
+        for (token, enabled) in self.0.annotated() {
+            let value = match token.instruction {
+                Instruction::Mul(a, b) => {
+                    let product = a.checked_mul(b);
+
+                    if enabled {
+                        running = running.zip(product).and_then(|(sum, p)| sum.checked_add(p));
+                    }
+
+                    product.map_or("overflow".to_string(), |p| p.to_string())
+                }
+                Instruction::Do | Instruction::Dont => "-".to_string(),
+            };
+            // Toggles are listed with the keywords they were read as
+            let instruction = match token.instruction {
+                Instruction::Do => self.0.config.enable.to_string(),
+                Instruction::Dont => self.0.config.disable.to_string(),
+                Instruction::Mul(..) => token.instruction.to_string(),
+            };
+            let running = running.map_or("overflow".to_string(), |sum| sum.to_string());
+
+            writeln!(
+                f,
+                "{:>8}  {:<16} {:<8} {:>12} {:>16}",
+                token.span.start, instruction, enabled, value, running
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Calculations {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::parse(s, ParserConfig::default()))
     }
 }
 
@@ -91,7 +271,7 @@ mod tests {
         let calcs = Calculations::from_str(EXAMPLE_1).expect("Failed to parse calculations");
         let sum = calcs.sum();
 
-        assert_eq!(sum, 161);
+        assert_eq!(sum, Ok(161));
     }
 
     #[test]
@@ -99,6 +279,307 @@ mod tests {
         let calcs = Calculations::from_str(EXAMPLE_2).expect("Failed to parse calculations");
         let sum = calcs.sum_conditional();
 
-        assert_eq!(sum, 48)
+        assert_eq!(sum, Ok(48))
+    }
+
+    #[test]
+    fn strict_syntax() {
+        let lex = |input, syntax| {
+            Lexer::new(input)
+                .with_config(ParserConfig {
+                    syntax,
+                    ..ParserConfig::default()
+                })
+                .map(|token| token.instruction)
+                .collect::<Vec<_>>()
+        };
+        let input = "mul(1,2,3)mul(+4,5)mul(1234,5)mul( 6,7)mul(8,9)";
+
+        assert_eq!(lex(input, Syntax::Strict), vec![Instruction::Mul(8, 9)]);
+        assert_eq!(
+            lex(input, Syntax::Lenient),
+            vec![
+                Instruction::Mul(1, 2),
+                Instruction::Mul(4, 5),
+                Instruction::Mul(1234, 5),
+                Instruction::Mul(8, 9),
+            ]
+        );
+
+        // Like the puzzle, every `mul(` is read on its own, even inside the arguments of another one
+        let nested = "mul(1,2,mul(3,4)";
+        assert_eq!(
+            lex(nested, Syntax::Lenient),
+            vec![Instruction::Mul(1, 2), Instruction::Mul(3, 4)]
+        );
+
+        let lenient = ParserConfig {
+            syntax: Syntax::Lenient,
+            ..ParserConfig::default()
+        };
+        let calcs = Calculations::parse(nested, lenient.clone());
+        assert_eq!(calcs.sum(), Ok(14));
+        assert!(calcs
+            .highlight()
+            .contains("\x1b[32mmul(1,2,mul(3,4)\x1b[0m"));
+
+        for chunk_size in 1..=8 {
+            let sums = StreamEvaluator::default()
+                .with_config(lenient.clone())
+                .with_chunk_size(chunk_size)
+                .evaluate(nested.as_bytes())
+                .expect("Failed to evaluate stream");
+            assert_eq!(sums.sum, 14, "chunk size: {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        let input = "mul(2,3)mul(18446744073709551615,2)";
+        let lenient = ParserConfig {
+            syntax: Syntax::Lenient,
+            ..ParserConfig::default()
+        };
+
+        let calcs = Calculations::parse(input, lenient);
+        assert_eq!(calcs.sum(), Err(OverflowError { span: 8..35 }));
+
+        let calcs = Calculations::parse(input, ParserConfig::default());
+        assert_eq!(calcs.sum(), Ok(6));
+    }
+
+    #[test]
+    fn interpreter() {
+        let standard = Interpreter::standard();
+        assert_eq!(standard.run(EXAMPLE_2).map(|state| state.value), Ok(48));
+
+        let extended = Interpreter::standard()
+            .register("add", 2, |state, args| {
+                state.value = state.value.checked_add(args[0] + args[1])?;
+                Some(())
+            })
+            .register("sub", 2, |state, args| {
+                state.value = state.value.checked_sub(args[0] + args[1])?;
+                Some(())
+            })
+            .register("reset", 0, |state, _| {
+                state.value = 0;
+                Some(())
+            });
+
+        let input = "mul(2,3)reset()add(4,5)]sub(1,2)mul(2,2)sub(1)";
+        assert_eq!(extended.run(input).map(|state| state.value), Ok(10));
+        assert_eq!(
+            extended.run("add(1,1)sub(2,1)"),
+            Err(OverflowError { span: 8..16 })
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let calcs = Calculations::from_str(EXAMPLE_2).expect("Failed to parse calculations");
+        let listing = calcs.listing().to_string();
+        let lines: Vec<Vec<&str>> = listing
+            .lines()
+            .skip(1)
+            .map(|line| line.split_whitespace().collect())
+            .collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], vec!["1", "mul(2,4)", "true", "8", "8"]);
+        assert_eq!(lines[1], vec!["20", "don't()", "false", "-", "8"]);
+        assert_eq!(lines[2], vec!["28", "mul(5,5)", "false", "25", "8"]);
+        assert_eq!(lines[5], vec!["64", "mul(8,5)", "true", "40", "48"]);
+    }
+
+    #[test]
+    fn highlight() {
+        let calcs = Calculations::from_str(EXAMPLE_2).expect("Failed to parse calculations");
+        let highlighted = calcs.highlight();
+
+        assert!(highlighted.contains("\x1b[32mmul(2,4)\x1b[0m"));
+        assert!(highlighted.contains("\x1b[31mmul(5,5)\x1b[0m"));
+        assert!(highlighted.contains("\x1b[36mdon't()\x1b[0m"));
+
+        let stripped = ["\x1b[32m", "\x1b[31m", "\x1b[36m", "\x1b[2m", "\x1b[0m"]
+            .iter()
+            .fold(highlighted, |text, code| text.replace(code, ""));
+        assert_eq!(stripped, EXAMPLE_2);
+    }
+
+    #[test]
+    fn streaming() {
+        for syntax in [Syntax::Strict, Syntax::Lenient] {
+            for chunk_size in 1..=16 {
+                let sums = StreamEvaluator::default()
+                    .with_config(ParserConfig {
+                        syntax,
+                        ..ParserConfig::default()
+                    })
+                    .with_chunk_size(chunk_size)
+                    .evaluate(EXAMPLE_2.as_bytes())
+                    .expect("Failed to evaluate stream");
+
+                assert_eq!(
+                    sums,
+                    Sums {
+                        sum: 161,
+                        sum_conditional: 48
+                    },
+                    "chunk size: {chunk_size}, syntax: {syntax:?}"
+                );
+            }
+        }
+
+        let overflow = StreamEvaluator::default()
+            .with_config(ParserConfig {
+                syntax: Syntax::Lenient,
+                ..ParserConfig::default()
+            })
+            .with_chunk_size(4)
+            .evaluate("mul(2,3)mul(18446744073709551615,2)".as_bytes());
+        assert!(matches!(
+            overflow,
+            Err(StreamError::Overflow(OverflowError { span })) if span == (8..35)
+        ));
+    }
+
+    #[test]
+    fn lenient_call_length() {
+        let long = format!("mul(2,3,{})mul(4,5", "x".repeat(300));
+        let lenient = ParserConfig {
+            syntax: Syntax::Lenient,
+            ..ParserConfig::default()
+        };
+        let stream = |input: &str, max_call_length| {
+            StreamEvaluator::default()
+                .with_config(lenient.clone())
+                .with_chunk_size(16)
+                .with_max_call_length(max_call_length)
+                .evaluate(input.as_bytes())
+                .expect("Failed to evaluate stream")
+                .sum
+        };
+
+        // Only streams limit how long calls can be
+        assert_eq!(Calculations::parse(&long, lenient.clone()).sum(), Ok(6));
+        assert_eq!(stream(&long, 256), 0);
+        assert_eq!(stream(&long, 512), 6);
+
+        // An unclosed call is given up on once it's too long, instead of buffering the rest of the stream
+        let unclosed = format!("mul(1,2{}mul(3,4)", "x".repeat(10_000));
+        assert_eq!(stream(&unclosed, 8), 12);
+        assert_eq!(stream(&unclosed, 256), 12);
+    }
+
+    #[test]
+    fn aggregations() {
+        let calcs = Calculations::from_str(EXAMPLE_2).expect("Failed to parse calculations");
+
+        let first = calcs.iter().next().expect("No multiplications");
+        assert_eq!((first.enabled(), first.a(), first.b()), (true, 2, 4));
+        assert_eq!(first.span(), 1..9);
+
+        assert_eq!(calcs.count_enabled(), 2);
+        assert_eq!(calcs.count_disabled(), 2);
+        assert_eq!(calcs.max_product(), Ok(Some(88)));
+        assert_eq!(
+            calcs.products_by_state(),
+            Ok(GroupedProducts {
+                enabled: vec![8, 40],
+                disabled: vec![25, 88],
+            })
+        );
+    }
+
+    #[test]
+    fn custom_toggles() {
+        let input = "mul(1,1)OFFmul(2,2)OFFmul(3,3)ONmul(4,4)ONmul(5,5)do()mul(6,6)";
+        let config = |toggles| ParserConfig {
+            enable: "ON".into(),
+            disable: "OFF".into(),
+            toggles,
+            ..ParserConfig::default()
+        };
+
+        let simple = Calculations::parse(input, config(ToggleMode::Simple));
+        assert_eq!(simple.sum_conditional(), Ok(1 + 16 + 25 + 36));
+
+        let stacked = Calculations::parse(input, config(ToggleMode::Stacked));
+        assert_eq!(stacked.sum_conditional(), Ok(1 + 25 + 36));
+
+        let listing = stacked.listing().to_string();
+        let instructions: Vec<&str> = listing
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_whitespace().nth(1))
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                "mul(1,1)", "OFF", "mul(2,2)", "OFF", "mul(3,3)", "ON", "mul(4,4)", "ON",
+                "mul(5,5)", "mul(6,6)"
+            ]
+        );
+
+        let sums = StreamEvaluator::default()
+            .with_config(config(ToggleMode::Stacked))
+            .with_chunk_size(3)
+            .evaluate(input.as_bytes())
+            .expect("Failed to evaluate stream");
+        assert_eq!(sums.sum_conditional, 1 + 25 + 36);
+    }
+
+    #[test]
+    fn prefix_toggles() {
+        let input = "mul(1,1)dontmul(2,3)domul(4,5)dont";
+        let config = ParserConfig {
+            enable: "do".into(),
+            disable: "dont".into(),
+            ..ParserConfig::default()
+        };
+
+        let instructions: Vec<Instruction> = Lexer::new(input)
+            .with_config(config.clone())
+            .map(|token| token.instruction)
+            .collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Instruction::Mul(1, 1),
+                Instruction::Dont,
+                Instruction::Mul(2, 3),
+                Instruction::Do,
+                Instruction::Mul(4, 5),
+                Instruction::Dont,
+            ]
+        );
+
+        for chunk_size in 1..=8 {
+            let sums = StreamEvaluator::default()
+                .with_config(config.clone())
+                .with_chunk_size(chunk_size)
+                .evaluate(input.as_bytes())
+                .expect("Failed to evaluate stream");
+            assert_eq!(sums.sum_conditional, 1 + 20, "chunk size: {chunk_size}");
+        }
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens: Vec<Token> = Lexer::new(EXAMPLE_2).collect();
+        let token = |instruction, span| Token { instruction, span };
+
+        assert_eq!(
+            tokens,
+            vec![
+                token(Instruction::Mul(2, 4), 1..9),
+                token(Instruction::Dont, 20..27),
+                token(Instruction::Mul(5, 5), 28..36),
+                token(Instruction::Mul(11, 8), 48..57),
+                token(Instruction::Do, 59..63),
+                token(Instruction::Mul(8, 5), 64..72),
+            ]
+        );
     }
 }
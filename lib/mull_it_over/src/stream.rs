@@ -0,0 +1,120 @@
+use crate::lexer::{Instruction, Lexer, ParserConfig, Toggles};
+use crate::{Multiplication, OverflowError};
+use std::io::{ErrorKind, Read};
+
+/// Default amount of bytes read from the stream at a time
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Default most bytes a call can span with [`crate::Syntax::Lenient`]
+const MAX_CALL_LENGTH: usize = 256;
+
+/// Both sums of the multiplications in corrupted memory
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Sums {
+    pub sum: usize,
+    pub sum_conditional: usize,
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(std::io::Error),
+    Overflow(OverflowError),
+}
+
+/// Evaluates corrupted memory from a reader chunk by chunk, without keeping the whole input or its instructions in memory.
+///
+/// Only the start of an instruction that may continue in the next chunk is kept between chunks.
+/// With [`crate::Syntax::Lenient`] that can be everything after a `mul(` without a closing parenthesis,
+/// so lenient calls longer than [`StreamEvaluator::with_max_call_length`] aren't read.
+pub struct StreamEvaluator {
+    config: ParserConfig,
+    chunk_size: usize,
+    max_call_length: usize,
+}
+
+impl Default for StreamEvaluator {
+    fn default() -> Self {
+        Self {
+            config: ParserConfig::default(),
+            chunk_size: CHUNK_SIZE,
+            max_call_length: MAX_CALL_LENGTH,
+        }
+    }
+}
+
+impl StreamEvaluator {
+    pub fn with_config(mut self, config: ParserConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets the most bytes a lenient call can span, which bounds how much of the stream is buffered
+    pub fn with_max_call_length(mut self, max_call_length: usize) -> Self {
+        self.max_call_length = max_call_length;
+        self
+    }
+
+    pub fn evaluate<R: Read>(&self, mut reader: R) -> Result<Sums, StreamError> {
+        let mut chunk = vec![0; self.chunk_size];
+        let mut buffer = Vec::new();
+        // Offset of the start of the buffer in the stream
+        let mut offset = 0;
+
+        let mut sums = Sums::default();
+        let mut toggles = Toggles::new(self.config.toggles);
+
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(read) => read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(StreamError::Io(err)),
+            };
+
+            buffer.extend_from_slice(&chunk[..read]);
+
+            let end_of_stream = read == 0;
+            let mut lexer = Lexer::from_stream(&buffer, end_of_stream)
+                .with_config(self.config.clone())
+                .with_max_call_length(self.max_call_length);
+
+            for token in &mut lexer {
+                toggles.apply(token.instruction);
+
+                let Instruction::Mul(a, b) = token.instruction else {
+                    continue;
+                };
+
+                let enabled = toggles.enabled();
+                let multiplication = Multiplication {
+                    enabled,
+                    a,
+                    b,
+                    span: token.span.start + offset..token.span.end + offset,
+                };
+
+                sums.sum = multiplication
+                    .add_to(sums.sum)
+                    .map_err(StreamError::Overflow)?;
+
+                if enabled {
+                    sums.sum_conditional = multiplication
+                        .add_to(sums.sum_conditional)
+                        .map_err(StreamError::Overflow)?;
+                }
+            }
+
+            if end_of_stream {
+                return Ok(sums);
+            }
+
+            let consumed = lexer.position();
+            buffer.drain(..consumed);
+            offset += consumed;
+        }
+    }
+}
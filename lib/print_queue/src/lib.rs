@@ -1,7 +1,45 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 
-type OrderingRules = HashMap<usize, Vec<usize>>;
+pub type OrderingRules = HashMap<usize, Vec<usize>>;
+
+/// How to pick the middle page of an update
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MiddlePolicy {
+    /// Updates with an even amount of pages have no middle page
+    #[default]
+    Strict,
+    /// Use the lower of the two middle pages for even-length updates
+    Lower,
+    /// Use the upper of the two middle pages for even-length updates
+    Upper,
+}
+
+/// Failed to find the middle page of an update
+#[derive(Debug, PartialEq, Eq)]
+pub enum MiddleError {
+    /// The update has no pages
+    Empty,
+    /// The update has an even amount of pages, so the middle is ambiguous
+    EvenLength(usize),
+}
+
+/// Why a print queue couldn't be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A page isn't a valid number
+    InvalidPage(String),
+    /// A rule doesn't have exactly two pages separated by `|`
+    InvalidRule(String),
+}
+
+/// Failed to parse a print queue
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The line of the rule or update, starting from 1
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
 
 #[derive(Debug)]
 pub struct Update {
@@ -9,9 +47,21 @@ pub struct Update {
 }
 
 impl Update {
-    pub fn middle_value(&self) -> usize {
-        let middle = self.pages.len() / 2;
-        self.pages[middle]
+    pub fn middle_value(&self, policy: MiddlePolicy) -> Result<usize, MiddleError> {
+        let len = self.pages.len();
+
+        if len == 0 {
+            return Err(MiddleError::Empty);
+        }
+
+        let middle = match policy {
+            _ if len % 2 == 1 => len / 2,
+            MiddlePolicy::Strict => return Err(MiddleError::EvenLength(len)),
+            MiddlePolicy::Lower => len / 2 - 1,
+            MiddlePolicy::Upper => len / 2,
+        };
+
+        Ok(self.pages[middle])
     }
 
     pub fn is_invalid(&self, rules: &OrderingRules) -> bool {
@@ -38,83 +88,274 @@ impl Update {
         let mut stack: Vec<usize> = Vec::with_capacity(self.pages.len());
 
         self.pages.iter().for_each(|page| {
-            if let Some(must_come_before) = rules.get(page) {
-                if let Some(before_pos) = must_come_before
+            // Pages without rules don't need to come before anything, so they're kept at the end
+            if let Some(before_pos) = rules.get(page).and_then(|must_come_before| {
+                must_come_before
                     .iter()
                     .filter_map(|num| stack.iter().position(|x| x == num))
                     .min()
-                {
-                    stack.insert(before_pos, *page);
-                } else {
-                    stack.push(*page);
-                }
+            }) {
+                stack.insert(before_pos, *page);
+            } else {
+                stack.push(*page);
             }
         });
 
-        let new = Self { pages: stack };
+        Self { pages: stack }
+    }
 
-        new
+    /// Whether the rules allow exactly one ordering of this update's pages
+    pub fn has_unique_ordering(&self, rules: &OrderingRules) -> bool {
+        self.orderings(rules, 2).count() == 1
+    }
+
+    /// Lazily enumerates every ordering of this update's pages that satisfies the rules,
+    /// yielding at most `limit` orderings.
+    ///
+    /// If the rules form a cycle between the pages, no orderings are yielded.
+    pub fn orderings(&self, rules: &OrderingRules, limit: usize) -> Orderings {
+        Orderings::new(&self.pages, rules, limit)
     }
 }
 
-impl FromStr for Update {
-    type Err = ();
+/// Iterator over the valid orderings of an update, see [`Update::orderings`]
+#[derive(Debug)]
+pub struct Orderings {
+    pages: Vec<usize>,
+    /// Indices of the pages that must come after the page at each index
+    successors: Vec<Vec<usize>>,
+    /// Amount of unplaced pages that must come before the page at each index
+    in_degree: Vec<usize>,
+    placed: Vec<bool>,
+    order: Vec<usize>,
+    /// Next page index to try at each depth of the search
+    cursor: Vec<usize>,
+    remaining: usize,
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+impl Orderings {
+    fn new(pages: &[usize], rules: &OrderingRules, limit: usize) -> Self {
+        let mut successors = vec![Vec::new(); pages.len()];
+        let mut in_degree = vec![0; pages.len()];
+
+        for (before_idx, page) in pages.iter().enumerate() {
+            let Some(must_come_before) = rules.get(page) else {
+                continue;
+            };
+
+            for (after_idx, _) in pages
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| must_come_before.contains(p))
+            {
+                successors[before_idx].push(after_idx);
+                in_degree[after_idx] += 1;
+            }
+        }
+
+        // With a cycle, the search would try every ordering of the other pages before giving up
+        let remaining = match Self::is_acyclic(&successors, &in_degree) {
+            true => limit,
+            false => 0,
+        };
+
+        Self {
+            pages: pages.to_vec(),
+            successors,
+            in_degree,
+            placed: vec![false; pages.len()],
+            order: Vec::with_capacity(pages.len()),
+            cursor: vec![0; pages.len() + 1],
+            remaining,
+        }
+    }
+
+    /// Whether every page can be placed, by repeatedly placing the pages nothing has to come before
+    fn is_acyclic(successors: &[Vec<usize>], in_degree: &[usize]) -> bool {
+        let mut in_degree = in_degree.to_vec();
+        let mut ready: Vec<usize> = (0..in_degree.len())
+            .filter(|&idx| in_degree[idx] == 0)
+            .collect();
+        let mut placed = 0;
+
+        while let Some(idx) = ready.pop() {
+            placed += 1;
+
+            for &after in &successors[idx] {
+                in_degree[after] -= 1;
+
+                if in_degree[after] == 0 {
+                    ready.push(after);
+                }
+            }
+        }
+
+        placed == in_degree.len()
+    }
+
+    fn place(&mut self, idx: usize) {
+        self.placed[idx] = true;
+        self.successors[idx]
+            .iter()
+            .for_each(|&after| self.in_degree[after] -= 1);
+        self.order.push(idx);
+    }
+
+    fn unplace_last(&mut self) {
+        let Some(idx) = self.order.pop() else {
+            return;
+        };
+
+        self.placed[idx] = false;
+        self.successors[idx]
+            .iter()
+            .for_each(|&after| self.in_degree[after] += 1);
+    }
+}
+
+impl Iterator for Orderings {
+    type Item = Update;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        if self.pages.is_empty() {
+            self.remaining = 0;
+            return Some(Update { pages: Vec::new() });
+        }
+
+        loop {
+            let depth = self.order.len();
+
+            let candidate = (self.cursor[depth]..self.pages.len())
+                .find(|&idx| !self.placed[idx] && self.in_degree[idx] == 0);
+
+            let Some(idx) = candidate else {
+                // Every choice at this depth has been tried, so backtrack
+                if depth == 0 {
+                    self.remaining = 0;
+                    return None;
+                }
+
+                self.unplace_last();
+                continue;
+            };
+
+            self.cursor[depth] = idx + 1;
+            self.cursor[depth + 1] = 0;
+            self.place(idx);
+
+            if self.order.len() == self.pages.len() {
+                let pages = self.order.iter().map(|&idx| self.pages[idx]).collect();
+                self.unplace_last();
+                self.remaining -= 1;
+
+                return Some(Update { pages });
+            }
+        }
+    }
+}
+
+impl Update {
+    fn parse_pages(s: &str) -> Result<Self, ParseErrorKind> {
         let pages = s
             .split(',')
-            .map(|s| s.parse().expect("Failed to parse ordering number"))
-            .collect();
+            .map(|s| parse_page(s.trim()))
+            .collect::<Result<_, _>>()?;
         Ok(Self { pages })
     }
 }
 
+fn parse_page(s: &str) -> Result<usize, ParseErrorKind> {
+    s.parse()
+        .map_err(|_| ParseErrorKind::InvalidPage(s.to_string()))
+}
+
+impl FromStr for Update {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_pages(s).map_err(|kind| ParseError { line: 1, kind })
+    }
+}
+
 #[derive(Debug)]
 pub struct PrintQueue {
     rules: OrderingRules,
     updates: Vec<Update>,
+    middle_policy: MiddlePolicy,
 }
 
 impl PrintQueue {
-    pub fn incorrect_updates_sum(&self) -> usize {
+    pub fn with_middle_policy(mut self, policy: MiddlePolicy) -> Self {
+        self.middle_policy = policy;
+        self
+    }
+
+    /// Updates where the rules allow more than one valid ordering, meaning their corrected middle page may be ambiguous
+    pub fn ambiguous_updates(&self) -> impl Iterator<Item = &Update> {
+        self.updates
+            .iter()
+            .filter(|u| u.orderings(&self.rules, 2).count() > 1)
+    }
+
+    /// Updates where the rules form a cycle between the pages, so they have no valid ordering at all
+    pub fn cyclic_updates(&self) -> impl Iterator<Item = &Update> {
+        self.updates
+            .iter()
+            .filter(|u| u.orderings(&self.rules, 1).next().is_none())
+    }
+
+    pub fn incorrect_updates_sum(&self) -> Result<usize, MiddleError> {
         self.updates
             .iter()
             .filter(|&u| u.is_invalid(&self.rules))
-            .map(|u| u.correct(&self.rules).middle_value())
+            .map(|u| u.correct(&self.rules).middle_value(self.middle_policy))
             .sum()
     }
 
-    pub fn correct_updates_sum(&self) -> usize {
+    pub fn correct_updates_sum(&self) -> Result<usize, MiddleError> {
         self.updates
             .iter()
             .filter(|&u| u.is_valid(&self.rules))
-            .map(|u| u.middle_value())
+            .map(|u| u.middle_value(self.middle_policy))
             .sum()
     }
 }
 
 impl FromStr for PrintQueue {
-    type Err = ();
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut rules = OrderingRules::new();
         let mut updates = Vec::new();
 
-        for line in s.lines().map(|l| l.trim()) {
+        for (idx, line) in s.lines().map(|l| l.trim()).enumerate() {
+            let error = |kind| ParseError {
+                line: idx + 1,
+                kind,
+            };
+
             if line.contains('|') {
-                let mut split = line
-                    .split('|')
-                    .map(|num| num.parse().expect("Failed to parse ordering number"));
-                let before = split.next().expect("No before number in rule");
-                let after = split.next().expect("No after number in rule");
+                let [before, after] = line.split('|').collect::<Vec<_>>()[..] else {
+                    return Err(error(ParseErrorKind::InvalidRule(line.to_string())));
+                };
+                let before = parse_page(before.trim()).map_err(error)?;
+                let after = parse_page(after.trim()).map_err(error)?;
                 let rule = rules.entry(before).or_default();
                 rule.push(after);
             } else if line.contains(",") {
-                updates.push(Update::from_str(line)?);
+                updates.push(Update::parse_pages(line).map_err(error)?);
             }
         }
 
-        Ok(Self { rules, updates })
+        Ok(Self {
+            rules,
+            updates,
+            middle_policy: MiddlePolicy::default(),
+        })
     }
 }
 
@@ -155,12 +396,105 @@ mod tests {
     #[test]
     fn solution_1() {
         let queue = PrintQueue::from_str(EXAMPLE).expect("Failed to parse printer queue");
-        assert_eq!(queue.correct_updates_sum(), 143);
+        assert_eq!(queue.correct_updates_sum(), Ok(143));
     }
 
     #[test]
     fn solution_2() {
         let queue = PrintQueue::from_str(EXAMPLE).expect("Failed to parse printer queue");
-        assert_eq!(queue.incorrect_updates_sum(), 123);
+        assert_eq!(queue.incorrect_updates_sum(), Ok(123));
+    }
+
+    #[test]
+    fn middle_policies() {
+        let update = Update::from_str("1,2,3,4").expect("Failed to parse update");
+        assert_eq!(
+            update.middle_value(MiddlePolicy::Strict),
+            Err(MiddleError::EvenLength(4))
+        );
+        assert_eq!(update.middle_value(MiddlePolicy::Lower), Ok(2));
+        assert_eq!(update.middle_value(MiddlePolicy::Upper), Ok(3));
+
+        let update = Update { pages: Vec::new() };
+        assert_eq!(
+            update.middle_value(MiddlePolicy::Upper),
+            Err(MiddleError::Empty)
+        );
+    }
+
+    #[test]
+    fn correct_keeps_pages_without_rules() {
+        let rules = OrderingRules::from([(2, vec![1])]);
+        let update = Update::from_str("1,3,2").expect("Failed to parse update");
+
+        assert!(update.is_invalid(&rules));
+        assert_eq!(update.correct(&rules).pages, vec![2, 1, 3]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            PrintQueue::from_str("1|2\n1|x\n\n1,2").err(),
+            Some(ParseError {
+                line: 2,
+                kind: ParseErrorKind::InvalidPage("x".to_string()),
+            })
+        );
+        assert_eq!(
+            PrintQueue::from_str("1|2|3").err(),
+            Some(ParseError {
+                line: 1,
+                kind: ParseErrorKind::InvalidRule("1|2|3".to_string()),
+            })
+        );
+        assert_eq!(
+            PrintQueue::from_str("1|2\n\n1,,2").err(),
+            Some(ParseError {
+                line: 3,
+                kind: ParseErrorKind::InvalidPage(String::new()),
+            })
+        );
+    }
+
+    #[test]
+    fn unique_orderings() {
+        let queue = PrintQueue::from_str(EXAMPLE).expect("Failed to parse printer queue");
+        assert_eq!(queue.ambiguous_updates().count(), 0);
+
+        let update = Update::from_str("97,13,75,29,47").expect("Failed to parse update");
+        let orderings: Vec<Vec<usize>> = update
+            .orderings(&queue.rules, 10)
+            .map(|u| u.pages)
+            .collect();
+        assert_eq!(orderings, vec![vec![97, 75, 47, 29, 13]]);
+    }
+
+    #[test]
+    fn ambiguous_orderings() {
+        let rules = OrderingRules::from([(1, vec![2, 3])]);
+        let update = Update::from_str("3,2,1").expect("Failed to parse update");
+
+        assert!(!update.has_unique_ordering(&rules));
+
+        let orderings: Vec<Vec<usize>> = update.orderings(&rules, 10).map(|u| u.pages).collect();
+        assert_eq!(orderings, vec![vec![1, 3, 2], vec![1, 2, 3]]);
+        assert_eq!(update.orderings(&rules, 1).count(), 1);
+
+        let cyclic = OrderingRules::from([(1, vec![2]), (2, vec![1])]);
+        assert_eq!(update.orderings(&cyclic, 10).count(), 0);
+
+        let queue = PrintQueue::from_str("1|2\n2|1\n\n1,2").expect("Failed to parse printer queue");
+        assert_eq!(queue.ambiguous_updates().count(), 0);
+        assert_eq!(queue.cyclic_updates().count(), 1);
+
+        // The cycle is found without trying every ordering of the other pages
+        let pages: Vec<String> = (1..=20)
+            .chain([100, 101])
+            .map(|page| page.to_string())
+            .collect();
+        let queue = PrintQueue::from_str(&format!("100|101\n101|100\n\n{}", pages.join(",")))
+            .expect("Failed to parse printer queue");
+        assert_eq!(queue.ambiguous_updates().count(), 0);
+        assert_eq!(queue.cyclic_updates().count(), 1);
     }
 }
@@ -1,77 +1,290 @@
-use std::collections::HashSet;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::BufRead;
 use std::str::FromStr;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Direction {
     Increasing,
     Decreasing,
 }
 
+/// Which directions the levels of a safe report may move in
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum AllowedDirections {
+    Increasing,
+    Decreasing,
+    /// Either direction, as long as the whole report moves the same way
+    #[default]
+    Either,
+}
+
+impl AllowedDirections {
+    fn directions(&self) -> &'static [Direction] {
+        match self {
+            Self::Increasing => &[Direction::Increasing],
+            Self::Decreasing => &[Direction::Decreasing],
+            Self::Either => &[Direction::Increasing, Direction::Decreasing],
+        }
+    }
+}
+
+/// Why a step between two adjacent levels breaks the rules
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub enum UnsafeReason {
+    /// The step goes the opposite way of the earlier steps
+    DirectionChange,
+    /// The report moves in a direction the rules don't allow
+    DisallowedDirection,
+    StepTooSmall,
+    StepTooLarge,
+    /// Two adjacent levels are equal, and plateaus aren't allowed
+    Plateau,
+    /// The report is too short to have any steps, and short reports are invalid
+    TooFewLevels,
+}
+
+impl Display for UnsafeReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::DirectionChange => "Direction change",
+            Self::DisallowedDirection => "Disallowed direction",
+            Self::StepTooSmall => "Step too small",
+            Self::StepTooLarge => "Step too large",
+            Self::Plateau => "Plateau",
+            Self::TooFewLevels => "Too few levels",
+        };
+
+        write!(f, "{reason}")
+    }
+}
+
+/// The first step of a report that breaks the rules
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Violation {
+    /// Index of the first level in the offending window
+    pub window: usize,
+    pub reason: UnsafeReason,
+}
+
+/// Fewest levels a report needs to have a step between two levels
+const MIN_LEVELS: usize = 2;
+
+/// How to treat reports with fewer than two levels, which have no steps to check.
+///
+/// This also applies to reports that become that short by removing levels when dampening.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub enum ShortReports {
+    /// Short reports are safe, as none of their steps break the rules
+    #[default]
+    Safe,
+    /// Short reports are never safe, and are rejected by a [`ReportReader`] using this policy
+    Invalid,
+}
+
+/// The rules a report must follow to be considered safe.
+///
+/// The default rules are the ones used by the Red-Nosed reactor:
+/// every step is between 1 and 3, and the levels are strictly increasing or decreasing.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SafetyRules {
+    /// Smallest allowed difference between two adjacent levels
+    pub min_step: usize,
+    /// Largest allowed difference between two adjacent levels
+    pub max_step: usize,
+    pub directions: AllowedDirections,
+    /// Whether two adjacent levels may be equal, regardless of `min_step`
+    pub allow_plateaus: bool,
+    pub short_reports: ShortReports,
+}
+
+impl Default for SafetyRules {
+    fn default() -> Self {
+        Self {
+            min_step: 1,
+            max_step: 3,
+            directions: AllowedDirections::default(),
+            allow_plateaus: false,
+            short_reports: ShortReports::default(),
+        }
+    }
+}
+
+impl SafetyRules {
+    fn is_safe_step(&self, x: usize, y: usize, direction: Direction) -> bool {
+        if x == y {
+            return self.allow_plateaus;
+        }
+
+        let diff = x.abs_diff(y);
+        diff >= self.min_step && diff <= self.max_step && Report::get_direction(x, y) == direction
+    }
+}
+
 #[derive(Debug)]
 pub struct Report(Vec<usize>);
 
 impl Report {
-    pub fn is_safe(&self, dampen: bool) -> bool {
-        let mut windows = self.0.windows(2);
+    pub fn is_safe(&self, rules: &SafetyRules, dampen: bool) -> bool {
+        self.is_safe_with_tolerance(rules, usize::from(dampen))
+    }
+
+    /// Checks whether the report can be made safe by removing at most `tolerance` levels.
+    ///
+    /// For each direction, this finds the fewest removals needed to keep a chain of levels that follow the rules,
+    /// only looking back `tolerance + 1` levels from each level, making it `O(n * tolerance)`.
+    pub fn is_safe_with_tolerance(&self, rules: &SafetyRules, tolerance: usize) -> bool {
+        // Removing more levels than the report has is the same as removing all of them
+        let tolerance = tolerance.min(self.0.len());
+
+        match rules.short_reports {
+            ShortReports::Safe if self.0.len() <= tolerance + 1 => return true,
+            ShortReports::Invalid if self.0.len() < MIN_LEVELS => return false,
+            _ => {}
+        }
 
-        // Check if any of the windows doesn't fall within the rules
-        if dampen {
-            let mut illegals = HashSet::new();
-            let mut increasing = HashSet::new();
-            let mut decreasing = HashSet::new();
+        rules
+            .directions
+            .directions()
+            .iter()
+            .any(|&direction| self.fewest_removals(rules, direction, tolerance) <= tolerance)
+    }
 
-            let first = self.0[0];
-            let last = self.0.last().expect("No last value");
-            let diff = first.abs_diff(*last);
-            let max_diff = (0..diff).step_by(3).size_hint().0;
+    /// Fewest removals needed to make the report safe in the given direction.
+    /// Anything above `tolerance` is reported as `tolerance + 1`.
+    fn fewest_removals(
+        &self,
+        rules: &SafetyRules,
+        direction: Direction,
+        tolerance: usize,
+    ) -> usize {
+        let levels = &self.0;
+        let too_many = tolerance + 1;
 
-            let mut biggest_diff = 0;
+        // Fewest removals needed for a safe chain of levels ending at each index,
+        // both for chains of any length, and for chains with at least one step
+        let mut chains: Vec<usize> = Vec::with_capacity(levels.len());
+        let mut stepped_chains: Vec<usize> = Vec::with_capacity(levels.len());
 
-            windows.enumerate().for_each(|(idx, nums)| {
-                let (x, y) = (nums[0], nums[1]);
-                let diff = x.abs_diff(y);
+        for (idx, &level) in levels.iter().enumerate() {
+            let mut fewest = too_many;
 
-                if diff > biggest_diff {
-                    biggest_diff = diff;
+            for prev in idx.saturating_sub(too_many)..idx {
+                if rules.is_safe_step(levels[prev], level, direction) {
+                    fewest = fewest.min(chains[prev] + (idx - prev - 1));
                 }
+            }
 
-                if !(diff != 0 && diff <= 3) {
-                    illegals.insert(idx);
-                }
+            stepped_chains.push(fewest);
+            // Removing every level before this one is always an option
+            chains.push(fewest.min(idx).min(too_many));
+        }
 
-                match Self::get_direction(x, y) {
-                    Direction::Increasing => {
-                        increasing.insert(idx);
-                    }
-                    Direction::Decreasing => {
-                        decreasing.insert(idx);
-                    }
-                }
+        let ends = match rules.short_reports {
+            ShortReports::Safe => &chains,
+            ShortReports::Invalid => &stepped_chains,
+        };
+
+        ends.iter()
+            .enumerate()
+            .map(|(idx, fewest)| fewest + (levels.len() - idx - 1))
+            .min()
+            .unwrap_or(0)
+            .min(too_many)
+    }
+
+    /// Finds the first window of two levels that breaks the rules, if any
+    pub fn first_violation(&self, rules: &SafetyRules) -> Option<Violation> {
+        if rules.short_reports == ShortReports::Invalid && self.0.len() < MIN_LEVELS {
+            return Some(Violation {
+                window: 0,
+                reason: UnsafeReason::TooFewLevels,
             });
+        }
 
-            if illegals.len() > 1 {
-                return false;
+        let mut report_direction = None;
+
+        self.0.windows(2).enumerate().find_map(|(window, nums)| {
+            let (x, y) = (nums[0], nums[1]);
+            let violation = |reason| Some(Violation { window, reason });
+
+            if x == y {
+                return match rules.allow_plateaus {
+                    true => None,
+                    false => violation(UnsafeReason::Plateau),
+                };
             }
 
-            if increasing.len() > 1 && decreasing.len() > 1 {
-                return false;
+            let direction = Self::get_direction(x, y);
+
+            match report_direction {
+                None if !rules.directions.directions().contains(&direction) => {
+                    return violation(UnsafeReason::DisallowedDirection);
+                }
+                None => report_direction = Some(direction),
+                Some(report_direction) if report_direction != direction => {
+                    return violation(UnsafeReason::DirectionChange);
+                }
+                Some(_) => {}
             }
 
-            if biggest_diff > max_diff {
-                return false;
+            let diff = x.abs_diff(y);
+
+            if diff < rules.min_step {
+                violation(UnsafeReason::StepTooSmall)
+            } else if diff > rules.max_step {
+                violation(UnsafeReason::StepTooLarge)
+            } else {
+                None
             }
+        })
+    }
 
-            true
-        } else {
-            let (first_x, first_y) = (self.0[0], self.0[1]);
-            let first_direction = Self::get_direction(first_x, first_y);
-            !windows.any(|nums| {
-                let (x, y) = (nums[0], nums[1]);
-                let diff = x.abs_diff(y);
+    /// The first level whose removal makes the report safe, see [`Report::removal_candidates`]
+    pub fn removal_candidate(&self, rules: &SafetyRules) -> Option<usize> {
+        self.removal_candidates(rules).first().copied()
+    }
 
-                first_direction != Self::get_direction(x, y) || !(diff != 0 && diff <= 3)
-            })
+    /// All levels whose removal makes the report safe, in ascending order.
+    ///
+    /// Reports that are already safe can have candidates too, such as their first or last level.
+    pub fn removal_candidates(&self, rules: &SafetyRules) -> Vec<usize> {
+        let levels = &self.0;
+        let len = levels.len();
+
+        if rules.short_reports == ShortReports::Invalid && len <= MIN_LEVELS {
+            return Vec::new();
+        }
+
+        let mut candidates = vec![false; len];
+
+        for &direction in rules.directions.directions() {
+            let is_safe_step =
+                |a: usize, b: usize| rules.is_safe_step(levels[a], levels[b], direction);
+
+            // Whether the levels before (and after) each index are safe on their own
+            let mut prefix = vec![true; len + 1];
+            for idx in 2..=len {
+                prefix[idx] = prefix[idx - 1] && is_safe_step(idx - 2, idx - 1);
+            }
+
+            let mut suffix = vec![true; len + 1];
+            for idx in (0..len.saturating_sub(1)).rev() {
+                suffix[idx] = suffix[idx + 1] && is_safe_step(idx, idx + 1);
+            }
+
+            for (idx, candidate) in candidates.iter_mut().enumerate() {
+                let bridged = idx == 0 || idx == len - 1 || is_safe_step(idx - 1, idx + 1);
+                *candidate |= prefix[idx] && suffix[idx + 1] && bridged;
+            }
         }
+
+        candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, &candidate)| candidate)
+            .map(|(idx, _)| idx)
+            .collect()
     }
 
     fn get_direction(x: usize, y: usize) -> Direction {
@@ -83,46 +296,231 @@ impl Report {
     }
 }
 
-impl FromStr for Report {
-    type Err = ();
+/// Why a line couldn't be parsed as a report
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    /// The line has no levels
+    Empty,
+    /// A level isn't a valid number
+    InvalidLevel(String),
+    /// The line has fewer than two levels, when short reports are invalid
+    TooFewLevels(usize),
+    /// Failed to read the line
+    Io(std::io::Error),
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let nums: Vec<usize> = s
+/// Failed to parse a report
+#[derive(Debug)]
+pub struct ParseError {
+    /// The line of the report, starting from 1
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl Report {
+    fn parse_levels(s: &str) -> Result<Self, ParseErrorKind> {
+        let nums = s
             .split_ascii_whitespace()
-            .map(|s| s.parse().expect("Failed to parse number"))
-            .collect();
+            .map(|s| {
+                s.parse()
+                    .map_err(|_| ParseErrorKind::InvalidLevel(s.to_string()))
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
 
         if nums.is_empty() {
-            return Err(());
+            return Err(ParseErrorKind::Empty);
         }
 
         Ok(Report(nums))
     }
 }
 
-pub struct Reports(Vec<Report>);
+impl From<Vec<usize>> for Report {
+    fn from(levels: Vec<usize>) -> Self {
+        Self(levels)
+    }
+}
+
+impl FromStr for Report {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_levels(s).map_err(|kind| ParseError { line: 1, kind })
+    }
+}
+
+/// Parses reports line by line from any reader, skipping blank lines.
+///
+/// Only a single line is kept in memory at a time, so arbitrarily large inputs can be processed.
+pub struct ReportReader<R> {
+    reader: R,
+    line: usize,
+    buffer: String,
+    done: bool,
+    short_reports: ShortReports,
+}
+
+impl<R: BufRead> ReportReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: 0,
+            buffer: String::new(),
+            done: false,
+            short_reports: ShortReports::default(),
+        }
+    }
+
+    /// Sets how to treat reports with fewer than two levels, rejecting them if they're invalid
+    pub fn with_short_reports(mut self, short_reports: ShortReports) -> Self {
+        self.short_reports = short_reports;
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for ReportReader<R> {
+    type Item = Result<Report, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            self.buffer.clear();
+            self.line += 1;
+
+            let kind = match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => break,
+                Ok(_) if self.buffer.trim().is_empty() => continue,
+                Ok(_) => match Report::parse_levels(&self.buffer) {
+                    Ok(report)
+                        if self.short_reports == ShortReports::Invalid
+                            && report.0.len() < MIN_LEVELS =>
+                    {
+                        ParseErrorKind::TooFewLevels(report.0.len())
+                    }
+                    Ok(report) => return Some(Ok(report)),
+                    Err(kind) => kind,
+                },
+                Err(err) => {
+                    // The reader can't be trusted to continue after failing
+                    self.done = true;
+                    ParseErrorKind::Io(err)
+                }
+            };
+
+            return Some(Err(ParseError {
+                line: self.line,
+                kind,
+            }));
+        }
+
+        self.done = true;
+        None
+    }
+}
+
+/// Safety statistics across all reports, see [`Reports::summary`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SafetySummary {
+    pub total: usize,
+    /// Reports that are safe without dampening
+    pub safe: usize,
+    /// Reports that are only safe when dampened
+    pub rescued_by_dampening: usize,
+    /// Reports that aren't safe without dampening, by the reason of their first violation
+    pub failures: BTreeMap<UnsafeReason, usize>,
+    /// Amount of reports with each amount of levels
+    pub lengths: BTreeMap<usize, usize>,
+    /// Amount of steps of each size between adjacent levels, across all reports
+    pub steps: BTreeMap<usize, usize>,
+}
+
+pub struct Reports {
+    reports: Vec<Report>,
+    rules: SafetyRules,
+}
 
 impl Reports {
+    /// Parses reports to check with the given rules, rejecting short reports if the rules make them invalid
+    pub fn parse(s: &str, rules: &SafetyRules) -> Result<Self, ParseError> {
+        Ok(Self {
+            reports: ReportReader::new(s.as_bytes())
+                .with_short_reports(rules.short_reports)
+                .collect::<Result<_, _>>()?,
+            rules: rules.clone(),
+        })
+    }
+
+    /// Checks the reports with other rules.
+    ///
+    /// Reports were already parsed, so short reports aren't rejected, use [`Reports::parse`] for that.
+    pub fn with_rules(mut self, rules: SafetyRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
     pub fn get_amount_of_safe_reports(&self, dampen: bool) -> usize {
-        self.0.iter().filter(|&a| a.is_safe(dampen)).count()
+        self.reports
+            .iter()
+            .filter(|&a| a.is_safe(&self.rules, dampen))
+            .count()
+    }
+
+    /// Counts the safe reports in a reader without keeping the reports in memory
+    pub fn count_safe_streaming<R: BufRead>(
+        reader: R,
+        rules: &SafetyRules,
+        dampen: bool,
+    ) -> Result<usize, ParseError> {
+        ReportReader::new(reader)
+            .with_short_reports(rules.short_reports)
+            .try_fold(0, |safe, report| {
+                Ok(safe + usize::from(report?.is_safe(rules, dampen)))
+            })
+    }
+
+    pub fn summary(&self) -> SafetySummary {
+        self.reports
+            .iter()
+            .fold(SafetySummary::default(), |mut summary, report| {
+                summary.total += 1;
+                *summary.lengths.entry(report.0.len()).or_default() += 1;
+
+                report.0.windows(2).for_each(|nums| {
+                    *summary.steps.entry(nums[0].abs_diff(nums[1])).or_default() += 1;
+                });
+
+                if report.is_safe(&self.rules, false) {
+                    summary.safe += 1;
+                    return summary;
+                }
+
+                if report.is_safe(&self.rules, true) {
+                    summary.rescued_by_dampening += 1;
+                }
+
+                if let Some(violation) = report.first_violation(&self.rules) {
+                    *summary.failures.entry(violation.reason).or_default() += 1;
+                }
+
+                summary
+            })
     }
 }
 
 impl FromStr for Reports {
-    type Err = ();
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(
-            s.lines()
-                .filter_map(|line| Report::from_str(line).ok())
-                .collect(),
-        ))
+        Self::parse(s, &SafetyRules::default())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::Reports;
+    use crate::{
+        AllowedDirections, ParseErrorKind, Report, ReportReader, Reports, SafetyRules,
+        ShortReports, UnsafeReason, Violation,
+    };
+    use std::collections::BTreeMap;
     use std::str::FromStr;
 
     const EXAMPLE: &str = r#"
@@ -144,4 +542,219 @@ mod tests {
         let reports = Reports::from_str(EXAMPLE).expect("failed to parse reports");
         assert_eq!(reports.get_amount_of_safe_reports(true), 4);
     }
+
+    #[test]
+    pub fn custom_rules() {
+        let rules = SafetyRules {
+            min_step: 0,
+            max_step: 5,
+            directions: AllowedDirections::Increasing,
+            allow_plateaus: true,
+            ..SafetyRules::default()
+        };
+        let reports = Reports::from_str(EXAMPLE)
+            .expect("failed to parse reports")
+            .with_rules(rules);
+
+        // Only `1 2 7 8 9` and `1 3 6 7 9` increase, and both stay within steps of 5
+        assert_eq!(reports.get_amount_of_safe_reports(false), 2);
+        // `1 3 2 4 5` becomes safe by removing the 3 (or the 2)
+        assert_eq!(reports.get_amount_of_safe_reports(true), 3);
+
+        let plateau = Report::from_str("1 1 2 3").expect("failed to parse report");
+        assert!(!plateau.is_safe(&SafetyRules::default(), false));
+        assert!(plateau.is_safe(
+            &SafetyRules {
+                allow_plateaus: true,
+                ..SafetyRules::default()
+            },
+            false
+        ));
+    }
+
+    #[test]
+    pub fn violations_and_removals() {
+        let rules = SafetyRules::default();
+        let explain = |report: &str| {
+            let report = Report::from_str(report).expect("failed to parse report");
+            (
+                report.first_violation(&rules),
+                report.removal_candidates(&rules),
+            )
+        };
+
+        let violation = |window, reason| Some(Violation { window, reason });
+
+        assert_eq!(
+            explain("1 2 7 8 9"),
+            (violation(1, UnsafeReason::StepTooLarge), vec![])
+        );
+        assert_eq!(
+            explain("1 3 2 4 5"),
+            (violation(1, UnsafeReason::DirectionChange), vec![1, 2])
+        );
+        assert_eq!(
+            explain("8 6 4 4 1"),
+            (violation(2, UnsafeReason::Plateau), vec![2, 3])
+        );
+        assert_eq!(explain("7 6 4 2 1"), (None, vec![0, 1, 3, 4]));
+    }
+
+    #[test]
+    pub fn summary() {
+        let reports = Reports::from_str(EXAMPLE).expect("failed to parse reports");
+        let summary = reports.summary();
+
+        assert_eq!(summary.total, 6);
+        assert_eq!(summary.safe, 2);
+        assert_eq!(summary.rescued_by_dampening, 2);
+        assert_eq!(
+            summary.failures,
+            BTreeMap::from([
+                (UnsafeReason::DirectionChange, 1),
+                (UnsafeReason::StepTooLarge, 2),
+                (UnsafeReason::Plateau, 1),
+            ])
+        );
+        assert_eq!(summary.lengths, BTreeMap::from([(5, 6)]));
+        assert_eq!(summary.steps.values().sum::<usize>(), 24);
+        assert_eq!(summary.steps.get(&0), Some(&1));
+    }
+
+    #[test]
+    pub fn streaming() {
+        let safe = Reports::count_safe_streaming(EXAMPLE.as_bytes(), &SafetyRules::default(), true)
+            .expect("failed to parse reports");
+        assert_eq!(safe, 4);
+
+        let errors: Vec<(usize, String)> = ReportReader::new("1 2 3\n\n4 x 6\n7 8 9\n".as_bytes())
+            .filter_map(|report| report.err())
+            .map(|err| match err.kind {
+                ParseErrorKind::InvalidLevel(level) => (err.line, level),
+                kind => panic!("unexpected error: {kind:?}"),
+            })
+            .collect();
+        assert_eq!(errors, vec![(3, "x".to_string())]);
+    }
+
+    #[test]
+    pub fn short_reports() {
+        let invalid = SafetyRules {
+            short_reports: ShortReports::Invalid,
+            ..SafetyRules::default()
+        };
+
+        for levels in [vec![], vec![5]] {
+            let report = Report::from(levels);
+            assert!(report.is_safe(&SafetyRules::default(), true));
+            assert!(!report.is_safe(&invalid, true));
+        }
+
+        let single = Report::from(vec![5]);
+        assert!(single.is_safe(&SafetyRules::default(), false));
+        assert_eq!(single.removal_candidates(&SafetyRules::default()), vec![0]);
+        assert_eq!(
+            single.first_violation(&invalid).map(|v| v.reason),
+            Some(UnsafeReason::TooFewLevels)
+        );
+        assert!(Report::from(vec![5, 6]).is_safe(&invalid, false));
+
+        let error = ReportReader::new("1 2\n3\n".as_bytes())
+            .with_short_reports(ShortReports::Invalid)
+            .find_map(|report| report.err())
+            .expect("short report was accepted");
+        assert_eq!(error.line, 2);
+        assert!(matches!(error.kind, ParseErrorKind::TooFewLevels(1)));
+
+        let error = Reports::parse("1 2\n\n3\n", &invalid)
+            .err()
+            .expect("short report was accepted");
+        assert_eq!(error.line, 3);
+        assert!(matches!(error.kind, ParseErrorKind::TooFewLevels(1)));
+    }
+
+    /// Small xorshift generator, so the property tests are reproducible without extra dependencies
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// Checks every way of removing at most `tolerance` levels, keeping at least `min_levels`
+    fn brute_force(levels: &[usize], tolerance: usize, min_levels: usize) -> bool {
+        let is_strictly_safe = |levels: &[usize]| {
+            if levels.len() < min_levels {
+                return false;
+            }
+
+            let increasing = levels.windows(2).all(|w| w[0] < w[1] && w[1] - w[0] <= 3);
+            let decreasing = levels.windows(2).all(|w| w[0] > w[1] && w[0] - w[1] <= 3);
+            increasing || decreasing
+        };
+
+        (0u32..1 << levels.len())
+            .filter(|mask| mask.count_ones() as usize <= tolerance)
+            .any(|mask| {
+                let kept: Vec<usize> = levels
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| mask & (1 << idx) == 0)
+                    .map(|(_, &level)| level)
+                    .collect();
+                is_strictly_safe(&kept)
+            })
+    }
+
+    #[test]
+    pub fn tolerance_matches_brute_force() {
+        let mut rng = XorShift(0x2024_1202);
+        let invalid = SafetyRules {
+            short_reports: ShortReports::Invalid,
+            ..SafetyRules::default()
+        };
+
+        for _ in 0..5000 {
+            let len = rng.next(10);
+            let mut level = 10 + rng.next(10);
+            let levels: Vec<usize> = (0..len)
+                .map(|_| {
+                    // Mostly small steps, so a good share of reports are (nearly) safe
+                    level = (level + rng.next(9)).saturating_sub(4);
+                    level
+                })
+                .collect();
+            let report = Report(levels.clone());
+
+            let candidates: Vec<usize> = (0..len)
+                .filter(|&idx| {
+                    let mut removed = levels.clone();
+                    removed.remove(idx);
+                    brute_force(&removed, 0, 0)
+                })
+                .collect();
+            assert_eq!(
+                report.removal_candidates(&SafetyRules::default()),
+                candidates,
+                "levels: {levels:?}"
+            );
+
+            for tolerance in (0..4).chain([usize::MAX]) {
+                assert_eq!(
+                    report.is_safe_with_tolerance(&SafetyRules::default(), tolerance),
+                    brute_force(&levels, tolerance, 0),
+                    "levels: {levels:?}, tolerance: {tolerance}"
+                );
+                assert_eq!(
+                    report.is_safe_with_tolerance(&invalid, tolerance),
+                    brute_force(&levels, tolerance, 2),
+                    "levels: {levels:?}, tolerance: {tolerance}, short reports invalid"
+                );
+            }
+        }
+    }
 }